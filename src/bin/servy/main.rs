@@ -2,8 +2,23 @@ use anyhow::{bail, Result};
 use std::str::FromStr;
 use tracing_subscriber::{filter, fmt, prelude::*};
 
+const USAGE: &str = "usage: servy [check <path|url>...]";
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.as_slice() {
+        [] => serve().await,
+        [command, locations @ ..] if command == "check" && !locations.is_empty() => {
+            check(locations).await
+        }
+        _ => bail!(USAGE),
+    }
+}
+
+/// Run the servy server, configured from the environment.
+async fn serve() -> Result<()> {
     let config = servy::Config::from_env()?;
 
     // Initialise logging with the log level from the config
@@ -18,3 +33,53 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Load redirects lists from local paths or URLs exactly as the server would, printing any
+/// problems found. Each location is a single source, even if it contains spaces, and where more
+/// than one is given they are merged with the first taking precedence. Returns an error if the
+/// lists cannot be loaded, or if any entries are invalid.
+async fn check(locations: &[String]) -> Result<()> {
+    let sources = locations
+        .iter()
+        .map(|location| {
+            // Plain paths are read from local disk, rather than being treated as a URL.
+            if location.contains("://") {
+                servy::source_from_location(location)
+            } else {
+                servy::source_from_location(&format!("file://{location}"))
+            }
+        })
+        .collect();
+
+    let config = servy::Config::default_with_redirects("");
+    let context = servy::AppContext::with_sources(config, sources);
+    let locations = context.redirects_urls().join(", ");
+    let redirects = context.refresh_redirects().await?;
+    let report = context.parse_report();
+
+    for diagnostic in &report.diagnostics {
+        let level = if diagnostic.kind.is_error() {
+            "error"
+        } else {
+            "warning"
+        };
+        println!("{level}: {diagnostic}");
+    }
+
+    let errors = report
+        .diagnostics
+        .iter()
+        .filter(|d| d.kind.is_error())
+        .count();
+    let warnings = report.diagnostics.len() - errors;
+    println!(
+        "{locations}: {} redirects defined, {errors} errors, {warnings} warnings",
+        redirects.len()
+    );
+
+    if report.has_errors() {
+        bail!("found {errors} invalid redirects in {locations}");
+    }
+
+    Ok(())
+}
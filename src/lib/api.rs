@@ -0,0 +1,263 @@
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    handlers::base_url,
+    redirects::{QueryPolicy, RedirectEntry, RedirectsDocument},
+    shorten::mint_link,
+    store::{validate_entry, RedirectStore},
+    AppContext,
+};
+
+/// The path beneath which the admin API is served.
+pub const API_PREFIX: &str = "/_api";
+
+/// An error returned by the admin API, which is rendered as a JSON object with an `error` field.
+#[derive(Debug)]
+pub(crate) struct ApiError(StatusCode, String);
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self(status, message.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        tracing::error!("admin API request failed: {e:#}");
+        Self(StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))
+    }
+}
+
+/// Construct a router for the admin API, which lists and changes the redirects in the writable
+/// store. Every request must carry the configured API token as a bearer token.
+pub fn api_router(context: AppContext) -> Router<AppContext> {
+    Router::new()
+        .route("/redirects", get(list_redirects).post(create_redirect))
+        .route(
+            "/redirects/*alias",
+            get(get_redirect)
+                .put(update_redirect)
+                .delete(delete_redirect),
+        )
+        .route("/shorten", post(shorten))
+        .route_layer(middleware::from_fn_with_state(context, require_token))
+}
+
+/// Reject requests which do not carry the configured API token in their `Authorization` header.
+async fn require_token(
+    State(context): State<AppContext>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let expected = context.config().api_token.as_deref();
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match (expected, provided) {
+        (Some(expected), Some(provided)) if tokens_match(expected, provided) => {
+            next.run(request).await
+        }
+        _ => {
+            let message = "a valid bearer token is required";
+            let mut response = ApiError::new(StatusCode::UNAUTHORIZED, message).into_response();
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            response
+        }
+    }
+}
+
+/// Compare two tokens in constant time, so that the expected token cannot be discovered by
+/// timing the responses to guesses.
+fn tokens_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Return the writable store, which the API is only served with.
+pub(crate) fn store(context: &AppContext) -> Result<&RedirectStore, ApiError> {
+    context.store().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "no redirects store is configured",
+        )
+    })
+}
+
+/// Reload the redirects after the store has changed, so that the change is served immediately.
+/// The change is already saved, so a failure to reload is only logged.
+pub(crate) async fn apply_changes(context: &AppContext) {
+    if let Err(e) = context.reload_store().await {
+        tracing::warn!("failed to reload redirects after changing the store: {e:#}");
+    }
+}
+
+/// List the redirects in the store.
+async fn list_redirects(
+    State(context): State<AppContext>,
+) -> Result<Json<RedirectsDocument>, ApiError> {
+    let redirects = store(&context)?.list().await?;
+    Ok(Json(RedirectsDocument { redirects }))
+}
+
+/// Return a single redirect from the store.
+async fn get_redirect(
+    Path(alias): Path<String>,
+    State(context): State<AppContext>,
+) -> Result<Json<RedirectEntry>, ApiError> {
+    match store(&context)?.get(&alias).await? {
+        Some(entry) => Ok(Json(entry)),
+        None => Err(not_found(&alias)),
+    }
+}
+
+/// A request to shorten a URL, which is given a newly minted short key.
+#[derive(Debug, Deserialize)]
+struct ShortenRequest {
+    url: String,
+    status: Option<u16>,
+    query: Option<QueryPolicy>,
+}
+
+/// A newly shortened link, with the public short URL which redirects to it.
+#[derive(Debug, Serialize)]
+struct ShortLink {
+    #[serde(flatten)]
+    entry: RedirectEntry,
+    short_url: String,
+}
+
+/// Add a redirect to a given URL to the store under a newly minted short key.
+async fn shorten(
+    State(context): State<AppContext>,
+    headers: HeaderMap,
+    Json(request): Json<ShortenRequest>,
+) -> Result<Response, ApiError> {
+    let store = store(&context)?;
+    let entry = RedirectEntry {
+        status: request.status,
+        query: request.query,
+        ..RedirectEntry::new("", &request.url)
+    };
+    // Validate with a placeholder alias, since the key has not been minted yet.
+    validate_entry(&RedirectEntry {
+        alias: "_".to_string(),
+        ..entry.clone()
+    })
+    .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+
+    let Some(entry) = mint_link(&context, store, entry).await? else {
+        let message = "failed to mint an unused short key, the key length may be too short";
+        return Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, message));
+    };
+    apply_changes(&context).await;
+
+    let short_url = format!("{}/{}", base_url(context.config(), &headers), entry.alias);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, short_url.clone())],
+        Json(ShortLink { entry, short_url }),
+    )
+        .into_response())
+}
+
+/// Add a new redirect to the store.
+async fn create_redirect(
+    State(context): State<AppContext>,
+    Json(entry): Json<RedirectEntry>,
+) -> Result<Response, ApiError> {
+    validate_entry(&entry)
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+
+    if !store(&context)?.create(entry.clone()).await? {
+        let message = format!("alias '{}' already exists", entry.alias);
+        return Err(ApiError::new(StatusCode::CONFLICT, message));
+    }
+    apply_changes(&context).await;
+
+    let location = format!("{API_PREFIX}/redirects/{}", entry.alias);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(entry),
+    )
+        .into_response())
+}
+
+/// Replace a redirect in the store.
+async fn update_redirect(
+    Path(alias): Path<String>,
+    State(context): State<AppContext>,
+    Json(entry): Json<RedirectEntry>,
+) -> Result<Json<RedirectEntry>, ApiError> {
+    if entry.alias != alias {
+        let message = format!("alias '{}' does not match the path", entry.alias);
+        return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, message));
+    }
+    validate_entry(&entry)
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+
+    if !store(&context)?.update(entry.clone()).await? {
+        return Err(not_found(&alias));
+    }
+    apply_changes(&context).await;
+
+    Ok(Json(entry))
+}
+
+/// Remove a redirect from the store.
+async fn delete_redirect(
+    Path(alias): Path<String>,
+    State(context): State<AppContext>,
+) -> Result<StatusCode, ApiError> {
+    if !store(&context)?.delete(&alias).await? {
+        return Err(not_found(&alias));
+    }
+    apply_changes(&context).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Construct the error returned when an alias is not in the store.
+fn not_found(alias: &str) -> ApiError {
+    let message = format!("alias '{alias}' does not exist");
+    ApiError::new(StatusCode::NOT_FOUND, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("secret", "secret", true)]
+    #[case("secret", "secreT", false)]
+    #[case("secret", "secrets", false)]
+    #[case("secret", "", false)]
+    fn test_tokens_match(#[case] expected: &str, #[case] provided: &str, #[case] matched: bool) {
+        assert_eq!(tokens_match(expected, provided), matched);
+    }
+}
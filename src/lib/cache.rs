@@ -0,0 +1,110 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{Context, Result};
+
+use crate::sources::Document;
+
+/// Save the redirects lists from each source to a cache file on local disk, so that they can be
+/// served if the sources are unavailable when Servy next starts.
+pub async fn save_documents(path: &Path, documents: &[Document]) -> Result<()> {
+    let contents = serde_json::to_vec(documents)?;
+    write_atomically(path, &contents)
+        .await
+        .with_context(|| format!("failed to write redirects cache: {}", path.display()))
+}
+
+/// Counts the temporary files written, so that each write uses a file of its own.
+static TMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Write a file on local disk. The contents are written alongside the file and renamed over it,
+/// so that a partially written file is never left behind. Each write uses its own temporary
+/// file, so that concurrent writes cannot interleave.
+pub(crate) async fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_os_string();
+    let id = TMP_FILES.fetch_add(1, Ordering::Relaxed);
+    tmp_path.push(format!(".{}.{id}.tmp", std::process::id()));
+
+    let result = match tokio::fs::write(&tmp_path, contents).await {
+        Ok(()) => tokio::fs::rename(&tmp_path, path).await,
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+    Ok(result?)
+}
+
+/// Load the redirects lists from a cache file written by [`save_documents`], in the order of the
+/// sources they were fetched from.
+pub async fn load_documents(path: &Path) -> Result<Vec<Document>> {
+    let contents = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("failed to read redirects cache: {}", path.display()))?;
+
+    serde_json::from_slice(&contents)
+        .with_context(|| format!("invalid redirects cache: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{redirects::Format, sources::Validators};
+
+    #[tokio::test]
+    async fn test_save_and_load_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects.cache");
+
+        let mut document = Document::new("redirects: []", Format::Yaml);
+        document.validators = Validators {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        };
+
+        let documents = vec![document, Document::new("foo http://foo.bar", Format::Lines)];
+        save_documents(&path, &documents).await.unwrap();
+        assert_eq!(load_documents(&path).await.unwrap(), documents);
+
+        // Saving again replaces the cache.
+        let documents = vec![Document::new("bar http://bar.baz", Format::Lines)];
+        save_documents(&path, &documents).await.unwrap();
+        assert_eq!(load_documents(&path).await.unwrap(), documents);
+    }
+
+    #[tokio::test]
+    async fn test_write_atomically_concurrent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects.cache");
+
+        let writes: Vec<_> = (0..20)
+            .map(|i| {
+                let path = path.clone();
+                tokio::spawn(async move {
+                    let contents = format!("{i}").repeat(10_000);
+                    write_atomically(&path, contents.as_bytes()).await
+                })
+            })
+            .collect();
+        for write in writes {
+            write.await.unwrap().unwrap();
+        }
+
+        // The file holds exactly one of the writes, and no temporary files are left behind.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!((0..20).any(|i| contents == format!("{i}").repeat(10_000)));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_load_documents_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects.cache");
+        assert!(load_documents(&path).await.is_err());
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(load_documents(&path).await.is_err());
+    }
+}
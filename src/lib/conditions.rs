@@ -0,0 +1,194 @@
+use axum::http::{header, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+/// An alternative target for a redirect, which is chosen when all of its conditions match the
+/// request. A target with no conditions always matches.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConditionalTarget {
+    pub url: String,
+    /// The platform of the client, as determined from its User-Agent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    /// A language tag which must match the client's most preferred language. A primary tag such
+    /// as `de` also matches regional variants like `de-AT`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// A string which must appear in the client's User-Agent (case-insensitive).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl ConditionalTarget {
+    /// Report whether all of the target's conditions match the given request headers.
+    pub fn matches(&self, headers: &HeaderMap) -> bool {
+        let user_agent = header_str(headers, header::USER_AGENT);
+
+        if let Some(platform) = self.platform {
+            if user_agent.and_then(Platform::from_user_agent) != Some(platform) {
+                return false;
+            }
+        }
+
+        if let Some(language) = &self.language {
+            let language = language.to_lowercase();
+            let matched = preferred_language(headers).is_some_and(|preferred| {
+                preferred == language || preferred.starts_with(&format!("{language}-"))
+            });
+            if !matched {
+                return false;
+            }
+        }
+
+        if let Some(needle) = &self.user_agent {
+            let matched =
+                user_agent.is_some_and(|ua| ua.to_lowercase().contains(&needle.to_lowercase()));
+            if !matched {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// The client platforms which can be detected from a User-Agent header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Ios,
+    Android,
+    Windows,
+    Macos,
+    Linux,
+}
+
+impl Platform {
+    /// Determine the platform of a client from its User-Agent header.
+    pub fn from_user_agent(user_agent: &str) -> Option<Platform> {
+        // Order matters: iOS user agents mention "Mac OS X", and Android user agents "Linux".
+        if ["iPhone", "iPad", "iPod"]
+            .iter()
+            .any(|d| user_agent.contains(d))
+        {
+            Some(Platform::Ios)
+        } else if user_agent.contains("Android") {
+            Some(Platform::Android)
+        } else if user_agent.contains("Windows") {
+            Some(Platform::Windows)
+        } else if user_agent.contains("Macintosh") || user_agent.contains("Mac OS X") {
+            Some(Platform::Macos)
+        } else if user_agent.contains("Linux") {
+            Some(Platform::Linux)
+        } else {
+            None
+        }
+    }
+}
+
+/// Return the client's most preferred language from the Accept-Language header, lowercased.
+/// Languages are ranked by their quality value, with ties broken by the order they appear in.
+pub fn preferred_language(headers: &HeaderMap) -> Option<String> {
+    let mut best: Option<(&str, f32)> = None;
+
+    for entry in header_str(headers, header::ACCEPT_LANGUAGE)?.split(',') {
+        let mut params = entry.split(';');
+        let tag = params.next().unwrap_or_default().trim();
+        let quality = params
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok());
+
+        let Some(quality) = quality else { continue };
+        if tag.is_empty() || tag == "*" || quality <= 0.0 {
+            continue;
+        }
+
+        if best.is_none_or(|(_, q)| quality > q) {
+            best = Some((tag, quality));
+        }
+    }
+
+    best.map(|(tag, _)| tag.to_lowercase())
+}
+
+/// Extract a header value as a string slice, if it is present and valid.
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const IPHONE: &str =
+        "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15";
+    const ANDROID: &str = "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36";
+    const WINDOWS: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) Gecko/20100101 Firefox/128.0";
+    const MACOS: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15";
+    const LINUX: &str = "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:128.0) Firefox/128.0";
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name.clone(), value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[rstest]
+    #[case(IPHONE, Some(Platform::Ios))]
+    #[case(ANDROID, Some(Platform::Android))]
+    #[case(WINDOWS, Some(Platform::Windows))]
+    #[case(MACOS, Some(Platform::Macos))]
+    #[case(LINUX, Some(Platform::Linux))]
+    #[case("curl/8.5.0", None)]
+    fn test_platform_from_user_agent(#[case] user_agent: &str, #[case] expected: Option<Platform>) {
+        assert_eq!(Platform::from_user_agent(user_agent), expected);
+    }
+
+    #[rstest]
+    #[case("de-DE,de;q=0.9,en;q=0.8", Some("de-de"))]
+    #[case("en;q=0.5, fr;q=0.9", Some("fr"))]
+    #[case("fr, de", Some("fr"))]
+    #[case("*, en;q=0.1", Some("en"))]
+    #[case("de;q=0, en;q=0.1", Some("en"))]
+    #[case("de;q=abc, en;q=0.1", Some("en"))]
+    #[case("", None)]
+    fn test_preferred_language(#[case] accept_language: &str, #[case] expected: Option<&str>) {
+        let headers = headers(&[(header::ACCEPT_LANGUAGE, accept_language)]);
+        assert_eq!(preferred_language(&headers), expected.map(String::from));
+    }
+
+    #[rstest]
+    #[case(Some(Platform::Ios), None, None, IPHONE, "en", true)]
+    #[case(Some(Platform::Ios), None, None, ANDROID, "en", false)]
+    #[case(None, Some("de"), None, LINUX, "de-AT,en;q=0.5", true)]
+    #[case(None, Some("de"), None, LINUX, "en,de;q=0.5", false)]
+    #[case(None, Some("de"), None, LINUX, "dev", false)]
+    #[case(None, None, Some("firefox"), LINUX, "en", true)]
+    #[case(None, None, Some("firefox"), IPHONE, "en", false)]
+    #[case(Some(Platform::Android), Some("fr"), None, ANDROID, "fr-FR", true)]
+    #[case(Some(Platform::Android), Some("fr"), None, ANDROID, "en", false)]
+    #[case(None, None, None, "", "", true)]
+    fn test_conditional_target_matches(
+        #[case] platform: Option<Platform>,
+        #[case] language: Option<&str>,
+        #[case] user_agent: Option<&str>,
+        #[case] request_user_agent: &str,
+        #[case] request_language: &str,
+        #[case] expected: bool,
+    ) {
+        let target = ConditionalTarget {
+            url: "http://example.com".to_string(),
+            platform,
+            language: language.map(String::from),
+            user_agent: user_agent.map(String::from),
+        };
+        let headers = headers(&[
+            (header::USER_AGENT, request_user_agent),
+            (header::ACCEPT_LANGUAGE, request_language),
+        ]);
+
+        assert_eq!(target.matches(&headers), expected);
+    }
+}
@@ -1,5 +1,11 @@
-use anyhow::{Context, Result};
-use std::env;
+use anyhow::{bail, Context, Result};
+use axum::http::StatusCode;
+use std::{env, path::PathBuf, time::Duration};
+
+use crate::{
+    redirects::{redirect_status, QueryPolicy},
+    signatures::parse_public_key,
+};
 
 const CONFIG_ENV_PREFIX: &str = "SERVY";
 
@@ -8,20 +14,70 @@ const CONFIG_LOG_LEVEL: &str = "LOG_LEVEL";
 const CONFIG_HOST: &str = "HOST";
 const CONFIG_PORT: &str = "PORT";
 const CONFIG_METRICS_PORT: &str = "METRICS_PORT";
+const CONFIG_DEFAULT_STATUS: &str = "DEFAULT_STATUS";
+const CONFIG_QUERY_POLICY: &str = "QUERY_POLICY";
+const CONFIG_MAX_ALIAS_DEPTH: &str = "MAX_ALIAS_DEPTH";
+const CONFIG_REFRESH_INTERVAL: &str = "REFRESH_INTERVAL";
+const CONFIG_MISS_REFRESH_INTERVAL: &str = "MISS_REFRESH_INTERVAL";
+const CONFIG_NEGATIVE_CACHE_TTL: &str = "NEGATIVE_CACHE_TTL";
+const CONFIG_CACHE_PATH: &str = "CACHE_PATH";
+const CONFIG_PUBLIC_KEY: &str = "PUBLIC_KEY";
+const CONFIG_STORE_PATH: &str = "STORE_PATH";
+const CONFIG_API_TOKEN: &str = "API_TOKEN";
+const CONFIG_PUBLIC_URL: &str = "PUBLIC_URL";
+const CONFIG_SHORT_KEY_LENGTH: &str = "SHORT_KEY_LENGTH";
 
 const DEFAULT_LOG_LEVEL: &str = "INFO";
 const DEFAULT_HOST: &str = "127.0.0.1";
 const DEFAULT_PORT: u16 = 8080;
 const DEFAULT_METRICS_PORT: u16 = 8081;
+const DEFAULT_STATUS: StatusCode = StatusCode::PERMANENT_REDIRECT;
+const DEFAULT_MAX_ALIAS_DEPTH: usize = 5;
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+const DEFAULT_MISS_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_SHORT_KEY_LENGTH: usize = 6;
 
 /// Defines the configuration for Servy.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Config {
     pub log_level: String,
+    /// The location of the redirects list. Several locations may be given, separated by
+    /// whitespace, in which case earlier sources take precedence over later ones.
     pub redirects_url: String,
     pub host: String,
     pub servy_port: u16,
     pub metrics_port: u16,
+    /// The status code used for redirects which do not specify their own.
+    pub default_status: StatusCode,
+    /// How request query strings are handled by redirects which do not specify their own policy.
+    pub query_policy: QueryPolicy,
+    /// The maximum number of local aliases a redirect may be resolved through.
+    pub max_alias_depth: usize,
+    /// How often the redirects are refreshed in the background. A zero interval disables
+    /// periodic refreshes.
+    pub refresh_interval: Duration,
+    /// The minimum time between refreshes triggered by requests for unknown paths.
+    pub miss_refresh_interval: Duration,
+    /// How long a path which was still unknown after a refresh is remembered, during which
+    /// requests for it do not trigger another refresh.
+    pub negative_cache_ttl: Duration,
+    /// A file in which the last redirects list fetched successfully is saved, and from which
+    /// redirects are loaded at startup if the source is unavailable.
+    pub cache_path: Option<PathBuf>,
+    /// A minisign public key. If set, each redirects list must be accompanied by a detached
+    /// signature made with the matching secret key, or it is rejected.
+    pub public_key: Option<String>,
+    /// A file holding the writable store of redirects managed at runtime. Its redirects take
+    /// precedence over those from `redirects_url`.
+    pub store_path: Option<PathBuf>,
+    /// The bearer token required to use the admin API. If unset, the API is disabled.
+    pub api_token: Option<String>,
+    /// The base URL at which Servy is publicly reachable, used to build short URLs. If unset, it
+    /// is derived from the `Host` header of each request.
+    pub public_url: Option<String>,
+    /// The number of characters in the short keys minted for new links.
+    pub short_key_length: usize,
 }
 
 impl Config {
@@ -39,6 +95,18 @@ impl Config {
             metrics_port,
             log_level: log_level.to_string(),
             redirects_url: redirects_url.to_string(),
+            default_status: DEFAULT_STATUS,
+            query_policy: QueryPolicy::default(),
+            max_alias_depth: DEFAULT_MAX_ALIAS_DEPTH,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            miss_refresh_interval: DEFAULT_MISS_REFRESH_INTERVAL,
+            negative_cache_ttl: DEFAULT_NEGATIVE_CACHE_TTL,
+            cache_path: None,
+            public_key: None,
+            store_path: None,
+            api_token: None,
+            public_url: None,
+            short_key_length: DEFAULT_SHORT_KEY_LENGTH,
         }
     }
     /// Default configuration constructor.
@@ -49,6 +117,18 @@ impl Config {
             metrics_port: DEFAULT_METRICS_PORT,
             log_level: String::from(DEFAULT_LOG_LEVEL),
             redirects_url: redirects_url.to_string(),
+            default_status: DEFAULT_STATUS,
+            query_policy: QueryPolicy::default(),
+            max_alias_depth: DEFAULT_MAX_ALIAS_DEPTH,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            miss_refresh_interval: DEFAULT_MISS_REFRESH_INTERVAL,
+            negative_cache_ttl: DEFAULT_NEGATIVE_CACHE_TTL,
+            cache_path: None,
+            public_key: None,
+            store_path: None,
+            api_token: None,
+            public_url: None,
+            short_key_length: DEFAULT_SHORT_KEY_LENGTH,
         }
     }
 
@@ -60,6 +140,47 @@ impl Config {
         let host = load_env_or_default(CONFIG_HOST, DEFAULT_HOST);
         let servy_port: u16 = load_env_or_default(CONFIG_PORT, "8080").parse::<u16>()?;
         let metrics_port: u16 = load_env_or_default(CONFIG_METRICS_PORT, "8081").parse::<u16>()?;
+        let default_status =
+            redirect_status(load_env_or_default(CONFIG_DEFAULT_STATUS, "308").parse::<u16>()?)?;
+        let query_policy = load_env_or_default(CONFIG_QUERY_POLICY, "drop").parse()?;
+        let max_alias_depth = load_env_or_default(CONFIG_MAX_ALIAS_DEPTH, "5").parse::<usize>()?;
+        let refresh_interval = Duration::from_secs(
+            load_env_or_default(CONFIG_REFRESH_INTERVAL, "300").parse::<u64>()?,
+        );
+        let miss_refresh_interval = Duration::from_secs(
+            load_env_or_default(CONFIG_MISS_REFRESH_INTERVAL, "10").parse::<u64>()?,
+        );
+        let negative_cache_ttl = Duration::from_secs(
+            load_env_or_default(CONFIG_NEGATIVE_CACHE_TTL, "60").parse::<u64>()?,
+        );
+        let cache_path = load_env(CONFIG_CACHE_PATH)
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let public_key = load_env(CONFIG_PUBLIC_KEY)
+            .ok()
+            .filter(|key| !key.is_empty());
+        if let Some(key) = &public_key {
+            parse_public_key(key)?;
+        }
+        let store_path = load_env(CONFIG_STORE_PATH)
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let api_token = load_env(CONFIG_API_TOKEN)
+            .ok()
+            .filter(|token| !token.is_empty());
+        let public_url = load_env(CONFIG_PUBLIC_URL)
+            .ok()
+            .filter(|url| !url.is_empty());
+        let short_key_length =
+            load_env_or_default(CONFIG_SHORT_KEY_LENGTH, "6").parse::<usize>()?;
+        if short_key_length == 0 {
+            bail!("{CONFIG_ENV_PREFIX}_{CONFIG_SHORT_KEY_LENGTH} must be greater than zero");
+        }
+        if api_token.is_some() && store_path.is_none() {
+            bail!("the admin API requires {CONFIG_ENV_PREFIX}_{CONFIG_STORE_PATH} to be set");
+        }
 
         Ok(Config {
             redirects_url,
@@ -67,9 +188,26 @@ impl Config {
             host,
             servy_port,
             metrics_port,
+            default_status,
+            query_policy,
+            max_alias_depth,
+            refresh_interval,
+            miss_refresh_interval,
+            negative_cache_ttl,
+            cache_path,
+            public_key,
+            store_path,
+            api_token,
+            public_url,
+            short_key_length,
         })
     }
 
+    /// Return the locations of the redirects sources, in order of precedence.
+    pub fn redirects_urls(&self) -> Vec<&str> {
+        self.redirects_url.split_whitespace().collect()
+    }
+
     /// Return a [`String`] representing the socket to bind the app server to.
     pub fn servy_socket(&self) -> String {
         let Config {
@@ -132,6 +270,18 @@ mod tests {
         assert_eq!(config.metrics_port, 8081);
         assert_eq!(config.log_level, "INFO");
         assert_eq!(config.redirects_url, test_redirects_url);
+        assert_eq!(config.default_status, StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(config.query_policy, QueryPolicy::Drop);
+        assert_eq!(config.max_alias_depth, DEFAULT_MAX_ALIAS_DEPTH);
+        assert_eq!(config.refresh_interval, DEFAULT_REFRESH_INTERVAL);
+        assert_eq!(config.miss_refresh_interval, DEFAULT_MISS_REFRESH_INTERVAL);
+        assert_eq!(config.negative_cache_ttl, DEFAULT_NEGATIVE_CACHE_TTL);
+        assert_eq!(config.cache_path, None);
+        assert_eq!(config.public_key, None);
+        assert_eq!(config.store_path, None);
+        assert_eq!(config.api_token, None);
+        assert_eq!(config.public_url, None);
+        assert_eq!(config.short_key_length, DEFAULT_SHORT_KEY_LENGTH);
     }
 
     #[rstest]
@@ -143,6 +293,30 @@ mod tests {
         assert_eq!(config.metrics_port, DEFAULT_METRICS_PORT);
         assert_eq!(config.log_level, DEFAULT_LOG_LEVEL);
         assert_eq!(config.redirects_url, test_redirects_url);
+        assert_eq!(config.default_status, DEFAULT_STATUS);
+        assert_eq!(config.query_policy, QueryPolicy::Drop);
+        assert_eq!(config.max_alias_depth, DEFAULT_MAX_ALIAS_DEPTH);
+        assert_eq!(config.refresh_interval, DEFAULT_REFRESH_INTERVAL);
+        assert_eq!(config.miss_refresh_interval, DEFAULT_MISS_REFRESH_INTERVAL);
+        assert_eq!(config.negative_cache_ttl, DEFAULT_NEGATIVE_CACHE_TTL);
+        assert_eq!(config.cache_path, None);
+        assert_eq!(config.public_key, None);
+        assert_eq!(config.store_path, None);
+        assert_eq!(config.api_token, None);
+        assert_eq!(config.public_url, None);
+        assert_eq!(config.short_key_length, DEFAULT_SHORT_KEY_LENGTH);
+    }
+
+    #[rstest]
+    #[case("http://example.com/redirects", vec!["http://example.com/redirects"])]
+    #[case(
+        "file:///etc/servy/local  http://example.com/redirects\n",
+        vec!["file:///etc/servy/local", "http://example.com/redirects"]
+    )]
+    #[case("", vec![])]
+    fn test_redirects_urls(#[case] redirects_url: &str, #[case] expected: Vec<&str>) {
+        let config = Config::default_with_redirects(redirects_url);
+        assert_eq!(config.redirects_urls(), expected);
     }
 
     #[rstest]
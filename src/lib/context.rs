@@ -1,29 +1,115 @@
 use std::{
-    sync::{Arc, RwLock},
-    time::SystemTime,
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
-use crate::{redirects::parse_redirects, Config, Redirects};
-use anyhow::Result;
-use reqwest::Client;
+use crate::{
+    cache::{load_documents, save_documents},
+    diagnostics::{DiagnosticKind, ParseReport},
+    metrics::{
+        DEGRADED, REDIRECTS_DEFINED, REDIRECT_DIAGNOSTICS, SIGNATURE_FAILURES,
+        SOURCE_REDIRECTS_DEFINED, SOURCE_UP,
+    },
+    redirects::parse_document,
+    signatures::{verify_signature, SIGNATURE_SUFFIX},
+    sources::{source_from_location, Document, RedirectSource},
+    store::RedirectStore,
+    Config, Redirects,
+};
+use anyhow::{bail, Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use rand::Rng;
+use tokio::sync::mpsc;
+
+/// How long to wait for a burst of filesystem events to settle before refreshing redirects.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// The largest random delay added to each periodic refresh, as a fraction of the interval, so
+/// that several instances started together do not all refresh at the same moment.
+const REFRESH_JITTER: f64 = 0.1;
+
+/// The maximum number of unknown paths remembered at once. When the limit is reached, expired
+/// paths are forgotten, and if that is not enough, all of them are.
+const MAX_MISSING_PATHS: usize = 10_000;
+
+/// How long to wait before first retrying a refresh which failed at startup.
+const RETRY_BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+
+/// The longest time to wait between retries of a refresh which failed at startup.
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(300);
 
 #[derive(Clone, Debug)]
 
 /// AppContext holds the context for a running Servy server, including the list of defined
-/// redirects and a common HTTP client to be used across redirect map refreshes.
+/// redirects and the sources from which the redirects map is refreshed.
 pub struct AppContext {
-    redirects_url: String,
+    config: Config,
+    /// The sources of redirects, in order of precedence.
+    sources: Vec<Arc<dyn RedirectSource>>,
+    /// The writable store of redirects, if one is configured. It is also the first source.
+    store: Option<Arc<RedirectStore>>,
     redirects: Arc<RwLock<Redirects>>,
-    http_client: Client,
+    report: Arc<RwLock<ParseReport>>,
+    /// The last redirects list fetched successfully from each source, if any.
+    documents: Arc<RwLock<Vec<Option<Document>>>>,
+    watching: Arc<AtomicBool>,
+    degraded: Arc<AtomicBool>,
+    /// Held while the redirects are refreshed, so that refreshes never overlap and an older
+    /// fetch cannot replace the redirects from a newer one.
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
+    /// When the last refresh triggered by a request for an unknown path finished.
+    last_miss_refresh: Arc<tokio::sync::Mutex<Option<Instant>>>,
+    /// Paths which were still unknown after a refresh, and when they were found to be.
+    missing: Arc<Mutex<HashMap<String, Instant>>>,
 }
 
 impl AppContext {
     /// Construct a new AppContext for a given Servy configuration.
     pub fn new(config: Config) -> Self {
+        let sources = config
+            .redirects_urls()
+            .into_iter()
+            .map(source_from_location)
+            .collect();
+        Self::with_sources(config, sources)
+    }
+
+    /// Construct a new AppContext which refreshes redirects from a custom source.
+    pub fn with_source(config: Config, source: Arc<dyn RedirectSource>) -> Self {
+        Self::with_sources(config, vec![source])
+    }
+
+    /// Construct a new AppContext which refreshes redirects from several custom sources. Where
+    /// more than one source defines an alias, the source which comes first takes precedence. If
+    /// a store is configured, it takes precedence over all of them.
+    pub fn with_sources(config: Config, mut sources: Vec<Arc<dyn RedirectSource>>) -> Self {
+        let store = config
+            .store_path
+            .as_ref()
+            .map(RedirectStore::new)
+            .map(Arc::new);
+        if let Some(store) = &store {
+            sources.insert(0, store.clone());
+        }
+
+        let documents = vec![None; sources.len()];
         Self {
-            redirects_url: config.redirects_url,
+            config,
+            sources,
+            store,
             redirects: Arc::new(RwLock::new(Redirects::new())),
-            http_client: Client::new(),
+            report: Arc::new(RwLock::new(ParseReport::new())),
+            documents: Arc::new(RwLock::new(documents)),
+            watching: Arc::new(AtomicBool::new(false)),
+            degraded: Arc::new(AtomicBool::new(false)),
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
+            last_miss_refresh: Arc::new(tokio::sync::Mutex::new(None)),
+            missing: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -32,52 +118,500 @@ impl AppContext {
         self.redirects.read().unwrap().clone()
     }
 
-    /// Return the URL used to fetch/refresh the redirects map.
+    /// Return the report of problems found when the redirects were last refreshed.
+    pub fn parse_report(&self) -> ParseReport {
+        self.report.read().unwrap().clone()
+    }
+
+    /// Return the writable store of redirects, if one is configured.
+    pub fn store(&self) -> Option<&RedirectStore> {
+        self.store.as_deref()
+    }
+
+    /// Return the configuration the AppContext was constructed with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Return the location used to fetch/refresh the redirects map. If there are several
+    /// sources, this is the location of the one with the highest precedence.
     pub fn redirects_url(&self) -> &str {
-        &self.redirects_url
+        self.sources.first().map_or("", |s| s.location())
     }
 
-    /// Refresh the redirects using the URL specified in the config.
+    /// Return the locations of each of the redirects sources, in order of precedence.
+    pub fn redirects_urls(&self) -> Vec<&str> {
+        self.sources.iter().map(|s| s.location()).collect()
+    }
+
+    /// Refresh the redirects from the sources specified in the config, fetching from each of them
+    /// concurrently. Sources which report that their redirects list is unchanged since the last
+    /// refresh are not parsed again, and if none have changed the current redirects are kept. If
+    /// a cache file is configured, the redirects lists are saved to it.
+    ///
+    /// Refreshes are made one at a time, so a refresh requested while another is in progress
+    /// waits for it to finish.
+    ///
+    /// If a public key is configured, each redirects list which has changed must have a valid
+    /// signature, or it is treated in the same way as a source which cannot be fetched.
+    ///
+    /// If a source cannot be fetched, the last redirects list fetched from it is used in its place
+    /// and an error is returned once the redirects from the other sources have been applied. If
+    /// nothing has been fetched from it before, it is skipped until it can be. The cache file is
+    /// only saved once there is a redirects list from every source.
     pub async fn refresh_redirects(&self) -> Result<Redirects> {
-        let redirects = Self::fetch_redirects(self.redirects_url(), &self.http_client).await?;
+        let _guard = self.refresh_lock.lock().await;
+        let previous = self.documents.read().unwrap().clone();
+
+        let handles: Vec<_> = self
+            .sources
+            .iter()
+            .zip(&previous)
+            .map(|(source, document)| {
+                let source = source.clone();
+                let validators = document
+                    .as_ref()
+                    .map(|d| d.validators.clone())
+                    .unwrap_or_default();
+                let public_key = self.config.public_key.clone();
+                tokio::spawn(async move {
+                    let document = source.fetch_if_changed(&validators).await?;
+                    if let (Some(document), Some(public_key)) = (&document, &public_key) {
+                        if source.requires_signature() {
+                            verify_document(source.as_ref(), public_key, document).await?;
+                        }
+                    }
+                    Ok::<_, anyhow::Error>(document)
+                })
+            })
+            .collect();
+
+        let mut documents = Vec::with_capacity(handles.len());
+        let mut errors = Vec::new();
+        let mut changed = false;
+        for ((handle, source), previous) in handles.into_iter().zip(&self.sources).zip(previous) {
+            let result = match handle.await {
+                Ok(result) => result,
+                Err(e) => Err(e.into()),
+            };
+
+            let labels = [("source", source.location().to_string())];
+            let up = if result.is_ok() { 1.0 } else { 0.0 };
+            metrics::gauge!(SOURCE_UP.namespaced_name(), &labels).set(up);
+
+            match result {
+                Ok(Some(document)) => {
+                    changed = true;
+                    documents.push(Some(document));
+                }
+                Ok(None) => documents.push(previous),
+                Err(e) => {
+                    errors.push(e.context(format!(
+                        "failed to refresh redirects from {}",
+                        source.location()
+                    )));
+                    documents.push(previous);
+                }
+            }
+        }
+
+        let redirects = if changed {
+            let redirects = self.apply_documents(&documents)?;
+            let complete: Option<Vec<Document>> = documents.into_iter().collect();
+            if let (Some(path), Some(documents)) = (&self.config.cache_path, complete) {
+                if let Err(e) = save_documents(path, &documents).await {
+                    tracing::warn!("{e:#}");
+                }
+            }
+            redirects
+        } else {
+            self.redirects()
+        };
+
+        if errors.len() > 1 {
+            for e in &errors {
+                tracing::warn!("{e:#}");
+            }
+            bail!("failed to refresh redirects from {} sources", errors.len());
+        }
+        if let Some(e) = errors.pop() {
+            return Err(e);
+        }
+
+        self.set_degraded(false);
+        Ok(redirects)
+    }
+
+    /// Reload the redirects after the writable store has changed. Only the store is read again,
+    /// and the redirects lists last fetched from the other sources are reused, so that changes to
+    /// the store are served without fetching from any of them.
+    pub async fn reload_store(&self) -> Result<Redirects> {
+        let store = self
+            .store
+            .as_ref()
+            .context("no redirects store is configured")?;
+        let _guard = self.refresh_lock.lock().await;
+
+        let document = store.fetch().await?;
+        let mut documents = self.documents.read().unwrap().clone();
+        // The store is always the first source.
+        documents[0] = Some(document);
+        let redirects = self.apply_documents(&documents)?;
+
+        let complete: Option<Vec<Document>> = documents.into_iter().collect();
+        if let (Some(path), Some(documents)) = (&self.config.cache_path, complete) {
+            if let Err(e) = save_documents(path, &documents).await {
+                tracing::warn!("{e:#}");
+            }
+        }
+        Ok(redirects)
+    }
+
+    /// Load the redirects from the cache file, for use when the sources are unavailable. Sources
+    /// which have already been fetched keep their current redirects list, and the cached lists
+    /// are used for the others. Servy is reported as degraded until the redirects are next
+    /// refreshed from every source.
+    pub async fn load_cached_redirects(&self) -> Result<Redirects> {
+        let path = self
+            .config
+            .cache_path
+            .as_deref()
+            .context("no redirects cache file is configured")?;
+        let _guard = self.refresh_lock.lock().await;
+
+        let documents = load_documents(path).await?;
+        if documents.len() != self.sources.len() {
+            bail!(
+                "redirects cache file was saved from {} sources, but {} are configured: {}",
+                documents.len(),
+                self.sources.len(),
+                path.display()
+            );
+        }
+        let current = self.documents.read().unwrap().clone();
+        let documents: Vec<_> = current
+            .into_iter()
+            .zip(documents)
+            .map(|(current, cached)| current.or(Some(cached)))
+            .collect();
+        let redirects = self.apply_documents(&documents)?;
+        self.set_degraded(true);
+
+        tracing::warn!("serving redirects from cache file: {}", path.display());
+        Ok(redirects)
+    }
+
+    /// Report whether a redirects list has been fetched from, or loaded from the cache for, at
+    /// least one of the sources.
+    pub fn has_documents(&self) -> bool {
+        self.documents.read().unwrap().iter().any(Option::is_some)
+    }
+
+    /// Parse the redirects lists from each source and merge them, in order of precedence, making
+    /// the result the current set of redirects and recording any problems found with them.
+    /// Sources without a redirects list are skipped.
+    fn apply_documents(&self, documents: &[Option<Document>]) -> Result<Redirects> {
+        let mut redirects = Redirects::new();
+        let mut report = ParseReport::new();
+
+        for (source, document) in self.sources.iter().zip(documents) {
+            let location = source.location();
+            let Some(document) = document else {
+                tracing::warn!(
+                    "no redirects list has been fetched from {location} yet, skipping it"
+                );
+                continue;
+            };
+            let (parsed, parsed_report) = parse_document(&document.contents, document.format)
+                .with_context(|| format!("invalid redirects list from {location}"))?;
+
+            for diagnostic in &parsed_report.diagnostics {
+                tracing::warn!("invalid redirect specification in {location}: {diagnostic}");
+            }
+            let labels = [("source", location.to_string())];
+            metrics::gauge!(SOURCE_REDIRECTS_DEFINED.namespaced_name(), &labels)
+                .set(parsed.len() as f64);
+
+            for (host, alias) in redirects.merge(parsed) {
+                let host = host.map(|h| format!(" for host {h}")).unwrap_or_default();
+                tracing::warn!(
+                    "alias '{alias}'{host} from {location} is already defined by a source with higher precedence, ignoring it"
+                );
+            }
+            report.merge(parsed_report);
+        }
+
+        let parsed = report.diagnostics.len();
+        redirects.reject_invalid_chains(self.config.max_alias_depth, &mut report);
+        for diagnostic in &report.diagnostics[parsed..] {
+            tracing::warn!("invalid redirect specification: {diagnostic}");
+        }
+
+        for kind in DiagnosticKind::ALL {
+            let labels = [("kind", kind.name())];
+            metrics::gauge!(REDIRECT_DIAGNOSTICS.namespaced_name(), &labels)
+                .set(report.count(kind) as f64);
+        }
+        match self.report.write() {
+            Ok(mut report_guard) => *report_guard = report,
+            Err(poisoned) => *poisoned.into_inner() = report,
+        }
+
         match self.redirects.write() {
             Ok(mut redirects_guard) => redirects_guard.clone_from(&redirects),
             Err(poisoned) => poisoned.into_inner().clone_from(&redirects),
         }
-        metrics::gauge!("servy_redirects_defined").set(redirects.len() as f64);
+        metrics::gauge!(REDIRECTS_DEFINED.namespaced_name()).set(redirects.len() as f64);
+
+        let documents = documents.to_vec();
+        match self.documents.write() {
+            Ok(mut documents_guard) => *documents_guard = documents,
+            Err(poisoned) => *poisoned.into_inner() = documents,
+        }
+        self.forget_missing();
         Ok(redirects)
     }
 
-    /// Fetch the list of redirects from the defined upstream.
-    async fn fetch_redirects(url: &str, client: &Client) -> Result<Redirects> {
-        tracing::info!("fetching redirects from url: {url}");
+    /// Report whether the redirects being served were loaded from the cache file, because the
+    /// source could not be reached.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
 
-        // Append a query parameter with current unix timestamp to break caching as required.
-        let url = format!(
-            "{url}?cachebust={}",
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)?
-                .as_secs()
-        );
+    /// Record whether the redirects being served were loaded from the cache file.
+    fn set_degraded(&self, degraded: bool) {
+        self.degraded.store(degraded, Ordering::Relaxed);
+        metrics::gauge!(DEGRADED.namespaced_name()).set(if degraded { 1.0 } else { 0.0 });
+    }
+
+    /// Keep trying to refresh the redirects in the background until a refresh succeeds, doubling
+    /// the delay after each failure up to [`RETRY_BACKOFF_MAX`].
+    pub fn retry_refresh(&self) {
+        let context = self.clone();
+        tokio::spawn(async move {
+            let mut delay = RETRY_BACKOFF_INITIAL;
+            loop {
+                tokio::time::sleep(delay).await;
 
-        let resp = client.get(url).send().await?.text().await?;
-        let map = parse_redirects(&resp);
-        Ok(map)
+                match context.refresh_redirects().await {
+                    Ok(_) => {
+                        tracing::info!("refreshed redirects from sources after failure");
+                        break;
+                    }
+                    Err(e) => {
+                        tracing::warn!("failed to refresh redirects, retrying in {delay:?}: {e:#}");
+                        delay = (delay * 2).min(RETRY_BACKOFF_MAX);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Refresh the redirects after a lookup for `path` missed, returning the redirects to retry
+    /// the lookup against, and whether they were refreshed since the lookup missed. Concurrent
+    /// misses share a single refresh, refreshes are made at most once per
+    /// `miss_refresh_interval`, and paths which were recently found to be unknown don't trigger a
+    /// refresh at all. In those cases, the current redirects are returned.
+    pub async fn refresh_on_miss(&self, path: &str) -> Result<(Redirects, bool)> {
+        if self.is_missing(path) {
+            return Ok((self.redirects(), false));
+        }
+
+        let requested = Instant::now();
+        let mut last_refresh = self.last_miss_refresh.lock().await;
+
+        // Skip the refresh if another finished while waiting for the lock, or too recently.
+        if let Some(last) = *last_refresh {
+            if last >= requested {
+                return Ok((self.redirects(), true));
+            }
+            if last.elapsed() < self.config.miss_refresh_interval {
+                return Ok((self.redirects(), false));
+            }
+        }
+
+        let result = self.refresh_redirects().await;
+        *last_refresh = Some(Instant::now());
+        result.map(|redirects| (redirects, true))
+    }
+
+    /// Remember that a path is unknown, even after refreshing the redirects, so that further
+    /// requests for it do not trigger a refresh until `negative_cache_ttl` has passed.
+    pub fn remember_missing(&self, path: &str) {
+        let ttl = self.config.negative_cache_ttl;
+        if ttl.is_zero() {
+            return;
+        }
+
+        let mut missing = self.missing.lock().unwrap_or_else(|e| e.into_inner());
+        if missing.len() >= MAX_MISSING_PATHS {
+            missing.retain(|_, found| found.elapsed() < ttl);
+            if missing.len() >= MAX_MISSING_PATHS {
+                missing.clear();
+            }
+        }
+        missing.insert(path.to_string(), Instant::now());
+    }
+
+    /// Report whether a path was recently found to be unknown.
+    fn is_missing(&self, path: &str) -> bool {
+        let missing = self.missing.lock().unwrap_or_else(|e| e.into_inner());
+        missing
+            .get(path)
+            .is_some_and(|found| found.elapsed() < self.config.negative_cache_ttl)
+    }
+
+    /// Forget all of the paths which were found to be unknown, as the redirects have changed.
+    fn forget_missing(&self) {
+        self.missing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Report whether the redirects sources are being watched for changes. When they are, the
+    /// redirects map is kept up to date by the watchers, rather than refreshed on a lookup miss.
+    pub fn is_watching(&self) -> bool {
+        self.watching.load(Ordering::Relaxed)
+    }
+
+    /// Watch each of the redirects sources which is backed by a local file for changes,
+    /// refreshing the redirects map whenever one is modified. Returns `false` unless every source
+    /// is backed by a local file, as the others still need to be refreshed some other way.
+    pub fn watch_redirects(&self) -> Result<bool> {
+        let paths: Vec<&Path> = self.sources.iter().filter_map(|s| s.watch_path()).collect();
+        for path in &paths {
+            self.watch_file(path)?;
+        }
+
+        let watching = !paths.is_empty() && paths.len() == self.sources.len();
+        self.watching.store(watching, Ordering::Relaxed);
+        Ok(watching)
+    }
+
+    /// Watch a single redirects file for changes, refreshing the redirects map whenever it or its
+    /// detached signature is modified. The signature may be replaced after the file itself, in
+    /// which case the first refresh fails verification and the second succeeds.
+    fn watch_file(&self, path: &Path) -> Result<()> {
+        // Watch the parent directory rather than the file itself, so that the watch survives
+        // the file being replaced by a rename (as is done by rsync and many editors).
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_name = path.file_name().map(|f| f.to_os_string());
+        let signature_name = file_name.clone().map(|mut f| {
+            f.push(SIGNATURE_SUFFIX);
+            f
+        });
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            let Ok(event) = res else { return };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            if event.paths.iter().any(|p| {
+                let name = p.file_name().map(|f| f.to_os_string());
+                name == file_name || name == signature_name
+            }) {
+                let _ = tx.send(());
+            }
+        })?;
+
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch redirects file: {}", path.display()))?;
+
+        tracing::info!("watching redirects file for changes: {}", path.display());
+
+        let context = self.clone();
+        tokio::spawn(async move {
+            // Move the watcher into the task so that it lives as long as the task does.
+            let _watcher = watcher;
+
+            while rx.recv().await.is_some() {
+                tokio::time::sleep(WATCH_DEBOUNCE).await;
+                while rx.try_recv().is_ok() {}
+
+                if let Err(e) = context.refresh_redirects().await {
+                    tracing::warn!("failed to reload redirects after file change: {e}");
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Refresh the redirects in the background at the configured interval, plus a random jitter.
+    /// If a refresh fails, the last good set of redirects continues to be served. Returns `false`
+    /// if periodic refreshes are disabled by a zero interval.
+    pub fn refresh_periodically(&self) -> bool {
+        let interval = self.config.refresh_interval;
+        if interval.is_zero() {
+            return false;
+        }
+
+        tracing::info!("refreshing redirects every {}s", interval.as_secs_f64());
+
+        let context = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(jittered(interval)).await;
+
+                if let Err(e) = context.refresh_redirects().await {
+                    tracing::warn!("periodic refresh of redirects failed: {e:#}");
+                }
+            }
+        });
+
+        true
     }
 }
 
+/// Fetch the signature of a redirects list from its source and verify it against a public key,
+/// counting any failure to do so.
+async fn verify_document(
+    source: &dyn RedirectSource,
+    public_key: &str,
+    document: &Document,
+) -> Result<()> {
+    let result = source
+        .fetch_signature()
+        .await
+        .and_then(|signature| verify_signature(public_key, &document.contents, &signature));
+
+    if result.is_err() {
+        let labels = [("source", source.location().to_string())];
+        metrics::counter!(SIGNATURE_FAILURES.namespaced_name(), &labels).increment(1);
+    }
+    result
+}
+
+/// Add a random jitter of up to [`REFRESH_JITTER`] of the interval to a refresh interval.
+fn jittered(interval: Duration) -> Duration {
+    interval.mul_f64(1.0 + rand::rng().random_range(0.0..REFRESH_JITTER))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Config;
+    use crate::{
+        redirects::RedirectEntry,
+        sources::{FileSource, StaticSource},
+        Config,
+    };
     use mockito::{Server, ServerGuard};
 
+    /// The public key matching the signature of the `signed_redirects` fixture.
+    const PUBLIC_KEY: &str = "RWTzMLesUreJW7gvFeTMLAHFxcQO1/yimECvWuWhpkh9tXGtgK2XRE/o";
+
     async fn mock_redirect_server() -> ServerGuard {
         let mut server = Server::new_async().await;
 
         server
             .mock("GET", "/mock_redirects")
-            .match_query(mockito::Matcher::Regex("cachebust=[0-9]+".into()))
+            .match_query(mockito::Matcher::Missing)
             .with_body("foo http://foo.bar\nbar http://bar.baz")
             .create_async()
             .await;
@@ -93,29 +627,525 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_fetch_redirects() {
-        let client = Client::new();
+    async fn test_refresh_redirects() {
         let server = mock_redirect_server().await;
         let url = format!("{}{}", server.url(), "/mock_redirects");
 
-        let redirects = AppContext::fetch_redirects(&url, &client).await.unwrap();
+        let context = AppContext::new(Config::default_with_redirects(&url));
+        context.refresh_redirects().await.unwrap();
+
+        let redirects = context.redirects();
 
         assert_eq!(redirects.len(), 2);
-        assert_eq!(redirects.get("foo").unwrap(), "http://foo.bar");
-        assert_eq!(redirects.get("bar").unwrap(), "http://bar.baz");
+        assert_eq!(redirects.get("foo").unwrap().url, "http://foo.bar");
+        assert_eq!(redirects.get("bar").unwrap().url, "http://bar.baz");
     }
 
     #[tokio::test]
-    async fn test_refresh_redirects() {
-        let server = mock_redirect_server().await;
-        let url = format!("{}{}", server.url(), "/mock_redirects");
+    async fn test_refresh_redirects_not_modified() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/mock_redirects")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_header("etag", "\"v1\"")
+            .with_body("foo http://foo.bar\nbar")
+            .create_async()
+            .await;
+        let not_modified = server
+            .mock("GET", "/mock_redirects")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .expect(1)
+            .create_async()
+            .await;
 
+        let url = format!("{}/mock_redirects", server.url());
         let context = AppContext::new(Config::default_with_redirects(&url));
         context.refresh_redirects().await.unwrap();
+        let redirects = context.refresh_redirects().await.unwrap();
+
+        not_modified.assert_async().await;
+        assert_eq!(redirects.get("foo").unwrap().url, "http://foo.bar");
+        assert_eq!(context.redirects(), redirects);
+        assert_eq!(context.parse_report().count(DiagnosticKind::FieldCount), 1);
+    }
+
+    #[tokio::test]
+    async fn test_load_cached_redirects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects");
+        std::fs::write(&path, "foo http://foo.bar").unwrap();
+
+        let mut config = Config::default_with_redirects("");
+        config.cache_path = Some(dir.path().join("redirects.cache"));
+
+        let context = AppContext::with_source(config.clone(), Arc::new(FileSource::new(&path)));
+        context.refresh_redirects().await.unwrap();
+        assert!(!context.is_degraded());
+
+        // A new instance whose source is unavailable can start from the cache.
+        std::fs::remove_file(&path).unwrap();
+        let context = AppContext::with_source(config, Arc::new(FileSource::new(&path)));
+        assert!(context.refresh_redirects().await.is_err());
+
+        context.load_cached_redirects().await.unwrap();
+        assert!(context.is_degraded());
+        assert_eq!(
+            context.redirects().get("foo").unwrap().url,
+            "http://foo.bar"
+        );
+
+        // Once the source is available again, refreshing from it clears the degraded state.
+        std::fs::write(&path, "bar http://bar.baz").unwrap();
+        context.retry_refresh();
+        for _ in 0..50 {
+            if !context.is_degraded() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(!context.is_degraded());
+        assert_eq!(
+            context.redirects().get("bar").unwrap().url,
+            "http://bar.baz"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_cached_redirects_without_cache() {
+        let context = AppContext::with_source(
+            Config::default_with_redirects(""),
+            Arc::new(StaticSource::new("")),
+        );
+        assert!(context.load_cached_redirects().await.is_err());
+        assert!(!context.is_degraded());
+    }
+
+    #[tokio::test]
+    async fn test_watch_redirects_unsupported_source() {
+        let context = AppContext::with_source(
+            Config::default_with_redirects(""),
+            Arc::new(StaticSource::new("")),
+        );
+        assert!(!context.watch_redirects().unwrap());
+        assert!(!context.is_watching());
+    }
+
+    #[tokio::test]
+    async fn test_watch_redirects_reloads_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects");
+        std::fs::write(&path, "foo http://foo.bar\nbar http://bar.baz").unwrap();
+
+        let context = AppContext::with_source(
+            Config::default_with_redirects(""),
+            Arc::new(FileSource::new(&path)),
+        );
+        context.refresh_redirects().await.unwrap();
+        assert!(context.watch_redirects().unwrap());
+        assert!(context.is_watching());
+
+        // Replace the file by renaming over it, as rsync does.
+        let tmp_path = dir.path().join(".redirects.tmp");
+        std::fs::write(&tmp_path, "baz http://baz.qux").unwrap();
+        std::fs::rename(&tmp_path, &path).unwrap();
+
+        for _ in 0..50 {
+            if context.redirects().get("baz").is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let redirects = context.redirects();
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(redirects.get("baz").unwrap().url, "http://baz.qux");
+    }
+
+    async fn counting_redirect_server(expected_hits: usize) -> (ServerGuard, mockito::Mock) {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/mock_redirects")
+            .match_query(mockito::Matcher::Any)
+            .with_body("foo http://foo.bar")
+            .expect(expected_hits)
+            .create_async()
+            .await;
+        (server, mock)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_on_miss_single_flight() {
+        let (server, mock) = counting_redirect_server(1).await;
+        let mut config =
+            Config::default_with_redirects(&format!("{}/mock_redirects", server.url()));
+        config.miss_refresh_interval = Duration::ZERO;
+        let context = AppContext::new(config);
+
+        let (a, b, c) = tokio::join!(
+            context.refresh_on_miss("a"),
+            context.refresh_on_miss("b"),
+            context.refresh_on_miss("c"),
+        );
+        for result in [a, b, c] {
+            let (redirects, refreshed) = result.unwrap();
+            assert!(redirects.get("foo").is_some());
+            assert!(refreshed);
+        }
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_on_miss_rate_limited() {
+        let (server, mock) = counting_redirect_server(1).await;
+        let config = Config::default_with_redirects(&format!("{}/mock_redirects", server.url()));
+        let context = AppContext::new(config);
+
+        assert!(context.refresh_on_miss("a").await.unwrap().1);
+        assert!(!context.refresh_on_miss("b").await.unwrap().1);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_on_miss_negative_cache() {
+        let (server, mock) = counting_redirect_server(1).await;
+        let mut config =
+            Config::default_with_redirects(&format!("{}/mock_redirects", server.url()));
+        config.miss_refresh_interval = Duration::ZERO;
+        let context = AppContext::new(config);
+
+        context.remember_missing("a");
+        context.refresh_on_miss("a").await.unwrap();
+        context.refresh_on_miss("b").await.unwrap();
+
+        // The successful refresh forgets the unknown paths, as the redirects may have changed.
+        assert!(!context.is_missing("a"));
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_remember_missing_disabled() {
+        let mut config = Config::default_with_redirects("");
+        config.negative_cache_ttl = Duration::ZERO;
+        let context = AppContext::new(config);
+
+        context.remember_missing("a");
+        assert!(!context.is_missing("a"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_periodically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects");
+        std::fs::write(&path, "foo http://foo.bar").unwrap();
+
+        let mut config = Config::default_with_redirects("");
+        config.refresh_interval = Duration::from_millis(50);
+        let context = AppContext::with_source(config, Arc::new(FileSource::new(&path)));
+        context.refresh_redirects().await.unwrap();
+        assert!(context.refresh_periodically());
+
+        std::fs::write(&path, "bar http://bar.baz").unwrap();
+        for _ in 0..50 {
+            if context.redirects().get("bar").is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(
+            context.redirects().get("bar").unwrap().url,
+            "http://bar.baz"
+        );
+
+        // When a refresh fails, the last good redirects are kept.
+        std::fs::remove_file(&path).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            context.redirects().get("bar").unwrap().url,
+            "http://bar.baz"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_periodically_disabled() {
+        let mut config = Config::default_with_redirects("");
+        config.refresh_interval = Duration::ZERO;
+        let context = AppContext::with_source(config, Arc::new(StaticSource::new("")));
+        assert!(!context.refresh_periodically());
+    }
+
+    #[test]
+    fn test_jittered() {
+        let interval = Duration::from_secs(100);
+        for _ in 0..100 {
+            let jittered = jittered(interval);
+            assert!(jittered >= interval);
+            assert!(jittered < Duration::from_secs(110));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_redirects_with_source() {
+        let source = StaticSource::new("foo http://foo.bar\nbar http://bar.baz");
+        let context = AppContext::with_source(Config::default_with_redirects(""), Arc::new(source));
+        context.refresh_redirects().await.unwrap();
 
         let redirects = context.redirects();
 
+        assert_eq!(context.redirects_url(), "inline");
         assert_eq!(redirects.len(), 2);
-        assert_eq!(redirects.get("foo").unwrap(), "http://foo.bar");
+        assert_eq!(redirects.get("foo").unwrap().url, "http://foo.bar");
+    }
+
+    #[test]
+    fn test_redirects_urls() {
+        let config =
+            Config::default_with_redirects("file:///etc/servy/redirects http://example.com");
+        let context = AppContext::new(config);
+        assert_eq!(context.redirects_url(), "file:///etc/servy/redirects");
+        assert_eq!(
+            context.redirects_urls(),
+            vec!["file:///etc/servy/redirects", "http://example.com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_redirects_multiple_sources() {
+        let context = AppContext::with_sources(
+            Config::default_with_redirects(""),
+            vec![
+                Arc::new(StaticSource::new("foo http://primary.com/foo")),
+                Arc::new(StaticSource::new(
+                    "foo http://secondary.com/foo\nbar http://secondary.com/bar",
+                )),
+            ],
+        );
+        let redirects = context.refresh_redirects().await.unwrap();
+
+        assert_eq!(redirects.len(), 2);
+        assert_eq!(redirects.get("foo").unwrap().url, "http://primary.com/foo");
+        assert_eq!(
+            redirects.get("bar").unwrap().url,
+            "http://secondary.com/bar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_redirects_source_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let primary = dir.path().join("primary");
+        let secondary = dir.path().join("secondary");
+        std::fs::write(&primary, "foo http://primary.com/foo").unwrap();
+
+        let context = AppContext::with_sources(
+            Config::default_with_redirects(""),
+            vec![
+                Arc::new(FileSource::new(&primary)),
+                Arc::new(FileSource::new(&secondary)),
+            ],
+        );
+
+        // Nothing has been fetched from the failing source yet, so it is skipped while the
+        // redirects from the others are applied.
+        assert!(context.refresh_redirects().await.is_err());
+        assert!(context.has_documents());
+        assert_eq!(context.redirects().len(), 1);
+        assert_eq!(
+            context.redirects().get("foo").unwrap().url,
+            "http://primary.com/foo"
+        );
+
+        std::fs::write(&secondary, "bar http://secondary.com/bar").unwrap();
+        context.refresh_redirects().await.unwrap();
+        assert_eq!(context.redirects().len(), 2);
+
+        // The last redirects from a failing source are kept, while the others are refreshed.
+        std::fs::remove_file(&secondary).unwrap();
+        std::fs::write(&primary, "baz http://primary.com/baz").unwrap();
+        assert!(context.refresh_redirects().await.is_err());
+
+        let redirects = context.redirects();
+        assert_eq!(redirects.len(), 2);
+        assert!(redirects.get("foo").is_none());
+        assert_eq!(redirects.get("baz").unwrap().url, "http://primary.com/baz");
+        assert_eq!(
+            redirects.get("bar").unwrap().url,
+            "http://secondary.com/bar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_redirects_mixed_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects");
+        std::fs::write(&path, "foo http://foo.bar").unwrap();
+
+        let context = AppContext::with_sources(
+            Config::default_with_redirects(""),
+            vec![
+                Arc::new(FileSource::new(&path)),
+                Arc::new(StaticSource::new("bar http://bar.baz")),
+            ],
+        );
+        assert!(!context.watch_redirects().unwrap());
+        assert!(!context.is_watching());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_redirects_signed() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects");
+        let signature_path = dir.path().join("redirects.minisig");
+        std::fs::copy(fixtures.join("signed_redirects"), &path).unwrap();
+
+        let mut config = Config::default_with_redirects("");
+        config.public_key = Some(PUBLIC_KEY.to_string());
+        let context = AppContext::with_source(config, Arc::new(FileSource::new(&path)));
+
+        // A list without a signature is rejected.
+        assert!(context.refresh_redirects().await.is_err());
+        assert!(context.redirects().is_empty());
+
+        std::fs::copy(fixtures.join("signed_redirects.minisig"), &signature_path).unwrap();
+        context.refresh_redirects().await.unwrap();
+        assert_eq!(context.redirects().len(), 2);
+
+        // A list which doesn't match its signature is rejected, keeping the current redirects.
+        std::fs::write(&path, "foo http://evil.example").unwrap();
+        assert!(context.refresh_redirects().await.is_err());
+        assert_eq!(
+            context.redirects().get("foo").unwrap().url,
+            "http://foo.bar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload_store() {
+        let (server, mock) = counting_redirect_server(1).await;
+        let dir = tempfile::tempdir().unwrap();
+        let mut config =
+            Config::default_with_redirects(&format!("{}/mock_redirects", server.url()));
+        config.store_path = Some(dir.path().join("store.json"));
+        let context = AppContext::new(config);
+        context.refresh_redirects().await.unwrap();
+
+        let store = context.store().unwrap();
+        store
+            .create(RedirectEntry::new("bar", "http://bar.baz"))
+            .await
+            .unwrap();
+        let redirects = context.reload_store().await.unwrap();
+
+        // The store change is served alongside the remote redirects, which are not fetched again.
+        assert_eq!(redirects.get("bar").unwrap().url, "http://bar.baz");
+        assert_eq!(redirects.get("foo").unwrap().url, "http://foo.bar");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_reload_store_source_never_fetched() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default_with_redirects("");
+        config.store_path = Some(dir.path().join("store.json"));
+        let context = AppContext::with_source(
+            config,
+            Arc::new(FileSource::new(dir.path().join("missing"))),
+        );
+        assert!(context.refresh_redirects().await.is_err());
+
+        let store = context.store().unwrap();
+        store
+            .create(RedirectEntry::new("bar", "http://bar.baz"))
+            .await
+            .unwrap();
+        context.reload_store().await.unwrap();
+        assert_eq!(
+            context.redirects().get("bar").unwrap().url,
+            "http://bar.baz"
+        );
+    }
+
+    /// A source which records how many fetches from it are in progress at once.
+    #[derive(Debug, Default)]
+    struct SlowSource {
+        active: std::sync::atomic::AtomicUsize,
+        max_active: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl RedirectSource for SlowSource {
+        async fn fetch(&self) -> Result<Document> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active.fetch_max(active, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(Document::new("foo http://foo.bar", crate::Format::Lines))
+        }
+
+        fn location(&self) -> &str {
+            "slow"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_redirects_serialized() {
+        let source = Arc::new(SlowSource::default());
+        let context = AppContext::with_source(Config::default_with_redirects(""), source.clone());
+
+        let (a, b, c) = tokio::join!(
+            context.refresh_redirects(),
+            context.refresh_redirects(),
+            context.refresh_redirects(),
+        );
+        for redirects in [a, b, c] {
+            assert!(redirects.unwrap().get("foo").is_some());
+        }
+        assert_eq!(source.max_active.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_watch_redirects_reloads_on_signature() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects");
+        let signature_path = dir.path().join("redirects.minisig");
+
+        let mut config = Config::default_with_redirects("");
+        config.public_key = Some(PUBLIC_KEY.to_string());
+        let context = AppContext::with_source(config, Arc::new(FileSource::new(&path)));
+        assert!(context.watch_redirects().unwrap());
+
+        // The list lands before its signature, so the reload it triggers fails verification.
+        std::fs::copy(fixtures.join("signed_redirects"), &path).unwrap();
+        tokio::time::sleep(WATCH_DEBOUNCE * 3).await;
+        assert!(context.redirects().is_empty());
+
+        // The signature landing then triggers another reload, which succeeds.
+        std::fs::copy(fixtures.join("signed_redirects.minisig"), &signature_path).unwrap();
+        for _ in 0..50 {
+            if !context.redirects().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(context.redirects().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_redirects_parse_report() {
+        let source = StaticSource::new("foo http://foo.bar\nbar\nfoo http://foo.baz");
+        let context = AppContext::with_source(Config::default_with_redirects(""), Arc::new(source));
+        assert!(context.parse_report().is_empty());
+
+        context.refresh_redirects().await.unwrap();
+
+        let report = context.parse_report();
+        assert_eq!(report.count(DiagnosticKind::FieldCount), 1);
+        assert_eq!(report.count(DiagnosticKind::DuplicateAlias), 1);
+        assert_eq!(
+            context.redirects().get("foo").unwrap().url,
+            "http://foo.baz"
+        );
     }
 }
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, fmt};
+
+/// The kinds of problem that can be found with an entry in a redirects list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// The entry's URL is neither an absolute URL nor the path of a local alias.
+    InvalidUrl,
+    /// A line does not have an alias and a URL, optionally preceded by a host.
+    FieldCount,
+    /// An option, such as a status code or query policy, is unknown or invalid.
+    InvalidOption,
+    /// A regex rule cannot be compiled.
+    InvalidRegex,
+    /// The alias is defined more than once. The last definition wins.
+    DuplicateAlias,
+    /// The alias is also the path of an embedded asset, which is always served in its place.
+    AssetConflict,
+    /// The alias points at other local aliases in a cycle, or through too long a chain.
+    AliasChain,
+}
+
+impl DiagnosticKind {
+    /// Every kind of diagnostic, used to report a count for each.
+    pub const ALL: [DiagnosticKind; 7] = [
+        DiagnosticKind::InvalidUrl,
+        DiagnosticKind::FieldCount,
+        DiagnosticKind::InvalidOption,
+        DiagnosticKind::InvalidRegex,
+        DiagnosticKind::DuplicateAlias,
+        DiagnosticKind::AssetConflict,
+        DiagnosticKind::AliasChain,
+    ];
+
+    /// Return a short identifier for the kind of diagnostic, as used in metric labels.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiagnosticKind::InvalidUrl => "invalid_url",
+            DiagnosticKind::FieldCount => "field_count",
+            DiagnosticKind::InvalidOption => "invalid_option",
+            DiagnosticKind::InvalidRegex => "invalid_regex",
+            DiagnosticKind::DuplicateAlias => "duplicate_alias",
+            DiagnosticKind::AssetConflict => "asset_conflict",
+            DiagnosticKind::AliasChain => "alias_chain",
+        }
+    }
+
+    /// Report whether the diagnostic is an error, meaning the entry was skipped. Other
+    /// diagnostics are warnings about entries which were still loaded.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            DiagnosticKind::DuplicateAlias | DiagnosticKind::AssetConflict
+        )
+    }
+}
+
+/// A problem found with a single entry in a redirects list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The line number of the entry in the line-based format, or its position in the list of
+    /// redirects in the structured formats. Both start from 1.
+    pub line: usize,
+    /// The raw text of the line, or the alias of a structured entry.
+    pub text: String,
+    pub kind: DiagnosticKind,
+    /// A human readable description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}: '{}'", self.line, self.message, self.text)
+    }
+}
+
+/// The problems found while parsing a redirects list.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    pub diagnostics: Vec<Diagnostic>,
+    /// The line and text each alias was defined by, keyed by host and alias, so that problems
+    /// found after parsing can be reported against the right line.
+    definitions: HashMap<(Option<String>, String), (usize, String)>,
+}
+
+impl ParseReport {
+    /// Construct an empty report.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a problem with the entry at a given line.
+    pub fn push(&mut self, line: usize, text: &str, kind: DiagnosticKind, message: &str) {
+        self.diagnostics.push(Diagnostic {
+            line,
+            text: text.to_string(),
+            kind,
+            message: message.to_string(),
+        });
+    }
+
+    /// Record the line and text which defined an alias, optionally scoped to a host.
+    pub(crate) fn define(&mut self, host: Option<&str>, alias: &str, line: usize, text: &str) {
+        let key = (host.map(String::from), alias.to_string());
+        self.definitions.insert(key, (line, text.to_string()));
+    }
+
+    /// Record a problem with the definition of an alias, optionally scoped to a host.
+    pub(crate) fn push_for_alias(
+        &mut self,
+        host: Option<&str>,
+        alias: &str,
+        kind: DiagnosticKind,
+        message: &str,
+    ) {
+        let key = (host.map(String::from), alias.to_string());
+        let (line, text) = match self.definitions.get(&key) {
+            Some((line, text)) => (*line, text.clone()),
+            None => (0, alias.to_string()),
+        };
+        self.push(line, &text, kind, message);
+    }
+
+    /// Append the diagnostics from the report of another redirects list, which is merged with a
+    /// lower precedence. Where both lists define an alias, this report's definition is kept.
+    pub fn merge(&mut self, other: ParseReport) {
+        self.diagnostics.extend(other.diagnostics);
+        for (key, definition) in other.definitions {
+            self.definitions.entry(key).or_insert(definition);
+        }
+    }
+
+    /// Return the number of diagnostics of a given kind.
+    pub fn count(&self, kind: DiagnosticKind) -> usize {
+        self.diagnostics.iter().filter(|d| d.kind == kind).count()
+    }
+
+    /// Report whether any entries were skipped because of errors.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.kind.is_error())
+    }
+
+    /// Report whether no problems were found.
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_report() {
+        let mut report = ParseReport::new();
+        assert!(report.is_empty());
+        assert!(!report.has_errors());
+
+        report.push(
+            3,
+            "foo http://foo.bar",
+            DiagnosticKind::DuplicateAlias,
+            "duplicate alias 'foo'",
+        );
+        assert!(!report.has_errors());
+
+        report.push(
+            5,
+            "bar",
+            DiagnosticKind::FieldCount,
+            "expected an alias and a URL",
+        );
+        report.push(
+            6,
+            "baz",
+            DiagnosticKind::FieldCount,
+            "expected an alias and a URL",
+        );
+        assert!(report.has_errors());
+        assert_eq!(report.count(DiagnosticKind::FieldCount), 2);
+        assert_eq!(report.count(DiagnosticKind::InvalidUrl), 0);
+        assert_eq!(
+            report.diagnostics[1].to_string(),
+            "line 5: expected an alias and a URL: 'bar'"
+        );
+    }
+}
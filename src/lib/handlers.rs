@@ -1,10 +1,17 @@
-use crate::{metrics::REDIRECTS_SERVED, AppContext};
+use crate::{
+    metrics::REDIRECTS_SERVED,
+    preview::{preview_request, render_preview},
+    qr::render_qr_svg,
+    redirects::{encode_path, RedirectMatch},
+    variants::variant_cookie,
+    AppContext, Config,
+};
 use anyhow::{bail, Error, Result};
 use axum::{
     body::Body,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
     http::{self, HeaderMap, Request, StatusCode},
-    response::{IntoResponse, Redirect, Response},
+    response::{Html, IntoResponse, Response},
 };
 use axum_embed::ServeEmbed;
 use rust_embed::RustEmbed;
@@ -19,20 +26,22 @@ struct Assets;
 /// Handle requests to the root URL "/" - delegating to the default_handler.
 pub async fn root_handler(
     headers: HeaderMap,
+    query: RawQuery,
     State(context): State<AppContext>,
 ) -> impl IntoResponse {
-    default_handler(Path("/".to_string()), State(context), headers).await
+    default_handler(Path("/".to_string()), query, State(context), headers).await
 }
 
 /// Default non-root path handler which attempts to first match a filename, then a redirect.
 pub async fn default_handler(
     Path(path): Path<String>,
+    RawQuery(query): RawQuery,
     State(context): State<AppContext>,
     headers: HeaderMap,
 ) -> impl IntoResponse {
     match handle_file(&headers, &path).await {
         Ok(file) => file.into_response(),
-        Err(_) => match handle_redirect(&path, &context).await {
+        Err(_) => match handle_redirect(&path, query.as_deref(), &headers, &context).await {
             Ok(redirect) => redirect.into_response(),
             Err(_) => handle_not_found(&headers).await,
         },
@@ -58,26 +67,106 @@ pub async fn error_handler(error: BoxError) -> impl IntoResponse {
     )
 }
 
-/// Construct a 308 Permanent Redirect if the given redirect is specified. If the initial lookup
-/// fails, then refresh the redirects map and try again. If the redirect is still not specified
-/// then return an error.
-async fn handle_redirect(path: &str, context: &AppContext) -> Result<Response> {
+/// Construct a redirect response if the given path matches a specified redirect, given the
+/// headers of the request. If the request asks for a preview, by adding a `+` to the path or a
+/// `preview` query parameter, a page describing the redirect is returned instead. Paths which
+/// end in `+` but are aliases themselves are redirected as usual.
+async fn handle_redirect(
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    context: &AppContext,
+) -> Result<Response> {
+    let redirects = context.redirects();
+    let preview = preview_request(path, query, |path| redirects.has_exact(path, headers));
+    let (path, query) = match &preview {
+        Some(preview) => (preview.path, preview.query.as_deref()),
+        None => (path, query),
+    };
+
+    lookup_redirect(path, headers, context, |redirect| match preview {
+        Some(_) => do_preview(redirect, path, query, headers, context),
+        None => do_redirect(redirect, query, context),
+    })
+    .await
+}
+
+/// Look up the redirect for a given path and pass it to `respond`. Redirects which point at
+/// other local aliases are resolved through to their final target. If the initial lookup fails,
+/// then refresh the redirects map and try again, unless the redirects source is being watched
+/// for changes. Refreshes on a miss are shared and rate limited by the context, and paths which
+/// are still unknown after a refresh are remembered so that they do not trigger another refresh
+/// for a while. If the redirect is still not specified then return an error.
+async fn lookup_redirect<T>(
+    path: &str,
+    headers: &HeaderMap,
+    context: &AppContext,
+    respond: impl FnOnce(&RedirectMatch) -> Result<T>,
+) -> Result<T> {
     let redirects = context.redirects();
-    let key = path.strip_suffix("/").unwrap_or(path).to_string();
+    let max_depth = context.config().max_alias_depth;
 
-    if let Some(value) = redirects.get(&key) {
-        do_redirect(&key, value)
+    if let Some(redirect) = redirects.resolve(path, headers, max_depth) {
+        respond(&redirect)
+    } else if context.is_watching() {
+        Err(Error::msg("no redirect found for key"))
     } else {
-        let redirects = context.refresh_redirects().await?;
+        // Sources which could be fetched have been applied even if the refresh failed, so the
+        // lookup is retried against whatever redirects are now being served.
+        let (redirects, refreshed) = match context.refresh_on_miss(path).await {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::warn!("failed to refresh redirects after a miss for {path}: {e:#}");
+                (context.redirects(), false)
+            }
+        };
 
-        if let Some(value) = redirects.get(&key) {
-            do_redirect(&key, value)
+        if let Some(redirect) = redirects.resolve(path, headers, max_depth) {
+            respond(&redirect)
         } else {
+            if refreshed {
+                context.remember_missing(path);
+            }
             Err(Error::msg("no redirect found for key"))
         }
     }
 }
 
+/// Handle requests for the QR code of an alias, which encodes the public short URL of the alias
+/// as an SVG image. Aliases are looked up in the same way as redirects, so that a QR code is
+/// only returned for an alias which would be redirected.
+pub async fn qr_handler(
+    Path(alias): Path<String>,
+    State(context): State<AppContext>,
+    headers: HeaderMap,
+) -> Response {
+    if lookup_redirect(&alias, &headers, &context, |_| Ok(()))
+        .await
+        .is_err()
+    {
+        return handle_not_found(&headers).await;
+    }
+
+    let short_url = short_url(context.config(), &headers, &alias);
+    match render_qr_svg(&short_url) {
+        Ok(svg) => ([(http::header::CONTENT_TYPE, "image/svg+xml")], svg).into_response(),
+        Err(e) => {
+            tracing::error!("failed to render QR code for {short_url}: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to render QR code",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Report whether a path would be served from the embedded assets. Assets are served in
+/// preference to redirects, so a redirect with the same alias can never be reached.
+pub(crate) fn is_asset(path: &str) -> bool {
+    Assets::get(path).is_some() || Assets::get(&format!("{path}/index.html")).is_some()
+}
+
 /// Construct a response for a given filepath. Use the embedded file server to return the
 /// appropriate file, recording the filename in the current span. If the path specified is a
 /// directory, and the directory contains an 'index.html' file, then serve it.
@@ -144,15 +233,92 @@ async fn handle_not_found(headers: &HeaderMap) -> Response<Body> {
     }
 }
 
-/// Construct and return a permanent redirect response for a given specified redirect.
-/// Ensure that the relevant metrics and spans are updated.
-fn do_redirect(key: &str, redirect: &str) -> Result<Response> {
-    Span::current().record("response.location", redirect);
+/// Construct and return a redirect response for a given specified redirect, using the
+/// redirect's own status code and query policy or the configured defaults. Ensure that the
+/// relevant metrics and spans are updated.
+fn do_redirect(
+    redirect: &RedirectMatch,
+    query: Option<&str>,
+    context: &AppContext,
+) -> Result<Response> {
+    let config = context.config();
+    let policy = redirect.redirect.query.unwrap_or(config.query_policy);
+    let location = policy.apply(&redirect.url, query);
+
+    Span::current().record("response.location", &location);
 
-    let labels = [("alias", key.to_string())];
+    let mut labels = vec![("alias", redirect.alias.to_string())];
+    if let Some(variant) = redirect.variant {
+        labels.push(("variant", variant.to_string()));
+    }
     metrics::counter!(REDIRECTS_SERVED.namespaced_name(), &labels).increment(1);
 
-    Ok(Redirect::permanent(redirect).into_response())
+    let status = redirect.redirect.status.unwrap_or(config.default_status);
+    let mut response = (status, [(http::header::LOCATION, location)]).into_response();
+
+    // Conditional redirects vary by request headers, so caches must take them into account.
+    if !redirect.redirect.targets.is_empty() {
+        response.headers_mut().insert(
+            http::header::VARY,
+            http::HeaderValue::from_static("Accept-Language, User-Agent"),
+        );
+    }
+
+    // Remember the variant a visitor was assigned so they keep seeing the same one, and make
+    // sure the response is never shared between visitors.
+    if let Some(cookie) = redirect
+        .variant
+        .and_then(|variant| variant_cookie(redirect.alias, variant))
+    {
+        let headers = response.headers_mut();
+        headers.insert(http::header::SET_COOKIE, cookie);
+        headers.insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("private, no-store"),
+        );
+    }
+
+    Ok(response)
+}
+
+/// Construct a response with the preview page for a given redirect, which shows where the
+/// redirect leads instead of following it.
+fn do_preview(
+    redirect: &RedirectMatch,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    context: &AppContext,
+) -> Result<Response> {
+    let config = context.config();
+    let policy = redirect.redirect.query.unwrap_or(config.query_policy);
+    let location = policy.apply(&redirect.url, query);
+    let status = redirect.redirect.status.unwrap_or(config.default_status);
+
+    let short_url = short_url(config, headers, path);
+    let page = render_preview(&short_url, redirect, &location, status, policy);
+
+    Ok(Html(page).into_response())
+}
+
+/// Return the base URL at which Servy is publicly reachable, which short URLs are built from.
+/// The configured `public_url` is used if set, otherwise the URL is derived from the `Host` and
+/// `X-Forwarded-Proto` headers of the request.
+pub(crate) fn base_url(config: &Config, headers: &HeaderMap) -> String {
+    if let Some(url) = &config.public_url {
+        return url.trim_end_matches('/').to_string();
+    }
+
+    let host = header_value(http::header::HOST, headers).unwrap_or_else(|| config.servy_socket());
+    let scheme = header_value(http::HeaderName::from_static("x-forwarded-proto"), headers)
+        .unwrap_or_else(|| "http".to_string());
+    format!("{scheme}://{host}")
+}
+
+/// Return the public short URL of an alias. The alias is percent-encoded, so that the URL leads
+/// back to it whatever characters it contains.
+pub(crate) fn short_url(config: &Config, headers: &HeaderMap, alias: &str) -> String {
+    format!("{}/{}", base_url(config, headers), encode_path(alias))
 }
 
 /// Extract a header value from a header map, or return an empty string if the header is absent.
@@ -184,4 +350,20 @@ mod tests {
         let absent_header_name = HeaderName::from_static("x-absent-header");
         assert_eq!(header_value(absent_header_name, &headers), None);
     }
+
+    #[test]
+    fn test_short_url() {
+        let mut config = Config::default_with_redirects("");
+        config.public_url = Some("https://go.example/".to_string());
+        let headers = HeaderMap::new();
+
+        assert_eq!(
+            short_url(&config, &headers, "docs/guide"),
+            "https://go.example/docs/guide"
+        );
+        assert_eq!(
+            short_url(&config, &headers, "c# 100%?café"),
+            "https://go.example/c%23%20100%25%3Fcaf%C3%A9"
+        );
+    }
 }
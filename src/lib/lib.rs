@@ -1,11 +1,36 @@
+mod api;
+mod cache;
+mod conditions;
 mod config;
 mod context;
+mod diagnostics;
 mod handlers;
+mod links;
+mod metadata;
 mod metrics;
+mod preview;
+mod qr;
 mod redirects;
 mod servy;
+mod shorten;
+mod signatures;
+mod sources;
+mod store;
+mod variants;
 
+pub use conditions::{ConditionalTarget, Platform};
 pub use config::Config;
 pub use context::AppContext;
-pub use redirects::Redirects;
+pub use diagnostics::{Diagnostic, DiagnosticKind, ParseReport};
+pub use metadata::Metadata;
+pub use redirects::{
+    parse_document, Format, QueryPolicy, Redirect, RedirectEntry, RedirectMatch, Redirects,
+    RedirectsDocument,
+};
 pub use servy::{metrics_router, run, servy_router};
+pub use sources::{
+    source_from_location, Document, FileSource, HttpSource, RedirectSource, StaticSource,
+    Validators,
+};
+pub use store::RedirectStore;
+pub use variants::WeightedVariant;
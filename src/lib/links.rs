@@ -0,0 +1,318 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handlers::base_url,
+    metadata::Metadata,
+    preview::escape_html,
+    redirects::{encode_path, is_exact_alias, Redirects},
+    AppContext,
+};
+
+/// The path of the searchable links directory page.
+pub const LINKS_PATH: &str = "/_/links";
+
+/// The path of the JSON listing of every redirect.
+pub const LINKS_JSON_PATH: &str = "/_/links.json";
+
+/// The path of the OpenSearch description document for the links directory.
+pub const OPENSEARCH_PATH: &str = "/_/opensearch.xml";
+
+/// A redirect as it is listed in the links directory.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Link<'a> {
+    /// The host the redirect is scoped to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<&'a str>,
+    pub alias: &'a str,
+    pub url: &'a str,
+    #[serde(flatten)]
+    pub metadata: &'a Metadata,
+}
+
+impl Link<'_> {
+    /// Report whether the link matches a search query. The query is split into words, each of
+    /// which must appear, ignoring case, in the alias, the target URL, the description or one of
+    /// the tags.
+    pub fn matches(&self, query: &str) -> bool {
+        let fields: Vec<String> = [self.alias, self.url]
+            .into_iter()
+            .chain(self.metadata.description.as_deref())
+            .chain(self.metadata.tags.iter().map(String::as_str))
+            .map(str::to_lowercase)
+            .collect();
+
+        query
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .all(|word| fields.iter().any(|field| field.contains(&word)))
+    }
+
+    /// Return the URL which follows the link. Links scoped to a host point at that host, as they
+    /// are not served on any other.
+    pub fn href(&self) -> String {
+        let path = encode_path(self.alias);
+        match self.host {
+            Some(host) => format!("//{host}/{path}"),
+            None => format!("/{path}"),
+        }
+    }
+}
+
+/// The query string of a request for the links directory page.
+#[derive(Debug, Default, Deserialize)]
+pub struct LinksQuery {
+    /// The search query, which filters the links shown.
+    #[serde(default)]
+    q: String,
+}
+
+/// The response body of the JSON links listing.
+#[derive(Debug, Serialize)]
+struct LinksListing<'a> {
+    links: Vec<Link<'a>>,
+}
+
+/// List every redirect, sorted by alias and then by host.
+pub fn links(redirects: &Redirects) -> Vec<Link<'_>> {
+    let mut links: Vec<Link> = redirects
+        .iter()
+        .map(|(host, alias, redirect)| Link {
+            host,
+            alias,
+            url: &redirect.url,
+            metadata: &redirect.metadata,
+        })
+        .collect();
+    links.sort_by(|a, b| (a.alias, a.host).cmp(&(b.alias, b.host)));
+    links
+}
+
+/// Handle requests for the JSON listing of every redirect along with its metadata, so that
+/// people can find the right alias without reading the redirects list itself.
+pub async fn links_json_handler(State(context): State<AppContext>) -> Response {
+    let redirects = context.redirects();
+    Json(LinksListing {
+        links: links(&redirects),
+    })
+    .into_response()
+}
+
+/// Handle requests for the links directory page, which lists every redirect and can be searched
+/// by alias, target and tags with the `q` query parameter.
+pub async fn links_handler(
+    Query(query): Query<LinksQuery>,
+    State(context): State<AppContext>,
+    headers: HeaderMap,
+) -> Html<String> {
+    let redirects = context.redirects();
+    let links: Vec<Link> = links(&redirects)
+        .into_iter()
+        .filter(|link| link.matches(&query.q))
+        .collect();
+    let base_url = base_url(context.config(), &headers);
+    Html(render_links(&base_url, &query.q, &links))
+}
+
+/// Handle requests for the OpenSearch description document, which lets browsers search the
+/// links directory from the address bar.
+pub async fn opensearch_handler(
+    State(context): State<AppContext>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let base_url = base_url(context.config(), &headers);
+    (
+        [(
+            header::CONTENT_TYPE,
+            "application/opensearchdescription+xml",
+        )],
+        render_opensearch(&base_url),
+    )
+}
+
+/// Render the HTML links directory page for a set of links, which have already been filtered by
+/// the given search query.
+fn render_links(base_url: &str, query: &str, links: &[Link]) -> String {
+    let rows: String = links
+        .iter()
+        .map(|link| {
+            let alias = escape_html(link.alias);
+            // Only exact aliases can be followed as they are written.
+            let alias = if is_exact_alias(link.alias) {
+                format!(r#"<a href="{}">{alias}</a>"#, escape_html(&link.href()))
+            } else {
+                alias
+            };
+            let tags: Vec<String> = link.metadata.tags.iter().map(|t| escape_html(t)).collect();
+            format!(
+                "        <tr><td>{alias}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(link.host.unwrap_or_default()),
+                escape_html(link.url),
+                escape_html(link.metadata.description.as_deref().unwrap_or_default()),
+                tags.join(", "),
+                escape_html(link.metadata.owner.as_deref().unwrap_or_default()),
+            )
+        })
+        .collect();
+
+    let base_url = escape_html(base_url);
+    let query = escape_html(query);
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <meta name="robots" content="noindex">
+    <title>Links</title>
+    <link rel="search" type="application/opensearchdescription+xml" title="Links" href="{base_url}{OPENSEARCH_PATH}">
+    <style>
+      body {{ font-family: sans-serif; margin: 2rem auto; padding: 0 1rem; max-width: 60rem; }}
+      table {{ border-collapse: collapse; width: 100%; }}
+      th, td {{ text-align: left; padding: 0.25rem 0.5rem; border-bottom: 1px solid #ddd; }}
+      td {{ word-break: break-all; }}
+    </style>
+  </head>
+  <body>
+    <h1>Links</h1>
+    <form action="{LINKS_PATH}" method="get">
+      <input type="search" name="q" value="{query}" placeholder="Search by alias, target or tag" autofocus>
+      <button type="submit">Search</button>
+    </form>
+    <p>{count} links</p>
+    <table>
+      <thead>
+        <tr><th>Alias</th><th>Host</th><th>Target</th><th>Description</th><th>Tags</th><th>Owner</th></tr>
+      </thead>
+      <tbody>
+{rows}      </tbody>
+    </table>
+  </body>
+</html>
+"#,
+        count = links.len(),
+    )
+}
+
+/// Render the OpenSearch description document, which points browsers at the links directory
+/// page for searches.
+fn render_opensearch(base_url: &str) -> String {
+    let base_url = escape_html(base_url);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>Links</ShortName>
+  <Description>Search the links at {base_url}</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <Url type="text/html" method="get" template="{base_url}{LINKS_PATH}?q={{searchTerms}}"/>
+</OpenSearchDescription>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redirects::{parse_document, Format};
+    use rstest::rstest;
+    use serde_json::json;
+
+    #[test]
+    fn test_links() {
+        let contents = json!({
+            "redirects": [
+                { "alias": "wiki", "url": "https://wiki.example", "tags": ["docs"] },
+                { "alias": "chat", "url": "https://chat.example", "owner": "it" },
+                { "host": "b.example", "alias": "chat", "url": "https://chat.b.example" },
+            ]
+        });
+        let (redirects, _) = parse_document(&contents.to_string(), Format::Json).unwrap();
+
+        let links = links(&redirects);
+        let aliases: Vec<_> = links.iter().map(|l| (l.host, l.alias)).collect();
+        assert_eq!(
+            aliases,
+            vec![(None, "chat"), (Some("b.example"), "chat"), (None, "wiki")]
+        );
+        assert_eq!(links[0].metadata.owner.as_deref(), Some("it"));
+        assert_eq!(links[2].metadata.tags, vec!["docs"]);
+    }
+
+    #[rstest]
+    #[case("", true)]
+    #[case("wiki", true)]
+    #[case("WIKI", true)]
+    #[case("example.com", true)]
+    #[case("onboarding", true)]
+    #[case("handbook", true)]
+    #[case("docs wiki", true)]
+    #[case("docs chat", false)]
+    #[case("platform", false)]
+    fn test_link_matches(#[case] query: &str, #[case] matched: bool) {
+        let metadata = Metadata {
+            description: Some("The team handbook".to_string()),
+            tags: vec!["docs".to_string(), "onboarding".to_string()],
+            owner: Some("platform".to_string()),
+            ..Metadata::default()
+        };
+        let link = Link {
+            host: None,
+            alias: "wiki",
+            url: "https://wiki.example.com",
+            metadata: &metadata,
+        };
+        assert_eq!(link.matches(query), matched);
+    }
+
+    #[test]
+    fn test_render_links() {
+        let metadata = Metadata {
+            tags: vec!["<docs>".to_string()],
+            ..Metadata::default()
+        };
+        let default = Metadata::default();
+        let links = [
+            Link {
+                host: None,
+                alias: "wiki",
+                url: "https://wiki.example.com/?a=1&b=2",
+                metadata: &metadata,
+            },
+            Link {
+                host: None,
+                alias: "github/*",
+                url: "https://github.com/jnsgruk",
+                metadata: &default,
+            },
+            Link {
+                host: Some("b.example"),
+                alias: "c# 100%?",
+                url: "https://c.example",
+                metadata: &default,
+            },
+        ];
+
+        let page = render_links("https://go.example", "<wiki>", &links);
+        assert!(page.contains(r#"<td><a href="/wiki">wiki</a></td>"#));
+        assert!(page.contains("<td>https://wiki.example.com/?a=1&amp;b=2</td>"));
+        assert!(page.contains("<td>&lt;docs&gt;</td>"));
+        assert!(page.contains("<td>github/*</td>"));
+        assert!(page.contains(
+            r#"<td><a href="//b.example/c%23%20100%25%3F">c# 100%?</a></td><td>b.example</td>"#
+        ));
+        assert!(page.contains(r#"value="&lt;wiki&gt;""#));
+        assert!(page.contains(r#"href="https://go.example/_/opensearch.xml""#));
+        assert!(page.contains("<p>3 links</p>"));
+    }
+
+    #[test]
+    fn test_render_opensearch() {
+        let document = render_opensearch("https://go.example");
+        assert!(document.contains(r#"template="https://go.example/_/links?q={searchTerms}""#));
+    }
+}
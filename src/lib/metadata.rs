@@ -0,0 +1,132 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{
+    de::{Error, IgnoredAny},
+    Deserialize, Deserializer, Serialize,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Optional information about a redirect which helps people find it, but does not change how it
+/// is served.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// A short description of where the redirect leads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Free-form labels used to group and search for redirects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The person or team responsible for the redirect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// When the redirect was created, as an RFC 3339 timestamp.
+    #[serde(
+        default,
+        serialize_with = "time::serde::rfc3339::option::serialize",
+        deserialize_with = "deserialize_timestamp",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<OffsetDateTime>,
+    /// When the redirect was last changed, as an RFC 3339 timestamp.
+    #[serde(
+        default,
+        serialize_with = "time::serde::rfc3339::option::serialize",
+        deserialize_with = "deserialize_timestamp",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated: Option<OffsetDateTime>,
+}
+
+impl Metadata {
+    /// Report whether no metadata is set.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// A timestamp as written in a redirects list: either an RFC 3339 string, or a native TOML
+/// datetime. Any other value is kept as invalid, so that it can be reported without failing to
+/// deserialise the rest of the list.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum RawTimestamp {
+    Text(String),
+    Toml(toml::value::Datetime),
+    Invalid(IgnoredAny),
+}
+
+impl RawTimestamp {
+    /// Parse the timestamp as an RFC 3339 date and time.
+    pub(crate) fn parse(self) -> Result<OffsetDateTime> {
+        let text = match self {
+            Self::Text(text) => text,
+            Self::Toml(datetime) => datetime.to_string(),
+            Self::Invalid(_) => bail!("invalid timestamp: expected an RFC 3339 string"),
+        };
+        OffsetDateTime::parse(&text, &Rfc3339)
+            .map_err(|e| anyhow!("invalid timestamp '{text}': {e}"))
+    }
+}
+
+/// Deserialise an optional RFC 3339 timestamp, which may also be given as a TOML datetime.
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<RawTimestamp>::deserialize(deserializer)?
+        .map(RawTimestamp::parse)
+        .transpose()
+        .map_err(|e| D::Error::custom(format!("{e:#}")))
+}
+
+/// Return the current time, truncated to whole seconds, for recording when a redirect changed.
+pub fn now() -> OffsetDateTime {
+    let now = OffsetDateTime::now_utc();
+    now.replace_nanosecond(0).unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_metadata_serde() {
+        let metadata: Metadata = serde_json::from_value(json!({
+            "description": "The team handbook",
+            "tags": ["docs", "onboarding"],
+            "owner": "platform",
+            "created": "2024-01-02T03:04:05Z",
+        }))
+        .unwrap();
+
+        assert_eq!(metadata.tags, vec!["docs", "onboarding"]);
+        assert_eq!(
+            metadata.created,
+            Some(OffsetDateTime::from_unix_timestamp(1704164645).unwrap())
+        );
+        assert_eq!(metadata.updated, None);
+        assert!(!metadata.is_empty());
+
+        let value = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(value["created"], "2024-01-02T03:04:05Z");
+        assert!(value.get("updated").is_none());
+    }
+
+    #[test]
+    fn test_metadata_invalid_timestamp() {
+        let result: Result<Metadata, _> = serde_json::from_value(json!({ "created": "yesterday" }));
+        assert!(result.is_err());
+        let result: Result<Metadata, _> = serde_json::from_value(json!({ "created": 1704164645 }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_metadata_is_empty() {
+        assert!(Metadata::default().is_empty());
+        assert!(serde_json::to_value(Metadata::default())
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -29,13 +29,45 @@ pub const REDIRECTS_SERVED: Metric = Metric {
     description: "The number of requests per redirect",
 };
 
-/// A gauge metric that represents the number of redirects defined at a given time.
+/// A gauge metric that represents the number of redirects defined at a given time, in total and
+/// by the source which defined them.
 pub const REDIRECTS_DEFINED: Metric = Metric {
     name: "redirects_defined",
     namespace: "servy",
     description: "The number of redirects defined",
 };
 
+/// A gauge metric that represents the number of problems found in the redirects list, by kind.
+pub const REDIRECT_DIAGNOSTICS: Metric = Metric {
+    name: "redirect_diagnostics",
+    namespace: "servy",
+    description: "The number of problems found in the redirects list, by kind",
+};
+
+/// A gauge metric that is 1 while Servy is serving redirects loaded from its cache, because the
+/// source could not be reached, and 0 otherwise.
+pub const DEGRADED: Metric = Metric {
+    name: "degraded",
+    namespace: "servy",
+    description: "Whether redirects are being served from the cache as the source is unavailable",
+};
+
+/// A gauge metric that is 1 for each source whose last refresh succeeded, and 0 for each whose
+/// last refresh failed.
+pub const SOURCE_UP: Metric = Metric {
+    name: "source_up",
+    namespace: "servy",
+    description: "Whether the last refresh of redirects from each source succeeded",
+};
+
+/// A gauge metric representing the number of redirects defined by each source, before they are
+/// merged.
+pub const SOURCE_REDIRECTS_DEFINED: Metric = Metric {
+    name: "source_redirects_defined",
+    namespace: "servy",
+    description: "The number of redirects defined by each source",
+};
+
 /// A counter metric keeping track of the number of HTTP responses by status code.
 pub const RESPONSE_STATUS: Metric = Metric {
     name: "response_status",
@@ -43,11 +75,31 @@ pub const RESPONSE_STATUS: Metric = Metric {
     description: "The status codes of HTTP responses",
 };
 
+/// A counter metric representing the number of redirects lists rejected from each source because
+/// their signature could not be verified.
+pub const SIGNATURE_FAILURES: Metric = Metric {
+    name: "signature_failures",
+    namespace: "servy",
+    description:
+        "The number of redirects lists rejected because their signature could not be verified",
+};
+
 /// An array of gauge metrics.
-const GAUGES: [Metric; 1] = [REDIRECTS_DEFINED];
+const GAUGES: [Metric; 5] = [
+    REDIRECTS_DEFINED,
+    REDIRECT_DIAGNOSTICS,
+    DEGRADED,
+    SOURCE_UP,
+    SOURCE_REDIRECTS_DEFINED,
+];
 
 /// An array of counter metrics.
-const COUNTERS: [Metric; 3] = [REQUESTS_TOTAL, REDIRECTS_SERVED, RESPONSE_STATUS];
+const COUNTERS: [Metric; 4] = [
+    REQUESTS_TOTAL,
+    REDIRECTS_SERVED,
+    RESPONSE_STATUS,
+    SIGNATURE_FAILURES,
+];
 
 /// Iterate over each of the defined metrics ensuring they are initialized and described
 /// per the definitions above.
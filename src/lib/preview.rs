@@ -0,0 +1,297 @@
+use axum::http::StatusCode;
+use time::format_description::well_known::Rfc3339;
+use url::form_urlencoded;
+
+use crate::redirects::{QueryPolicy, RedirectMatch};
+
+/// The query parameter which asks for the preview page of a redirect instead of following it.
+const PREVIEW_PARAM: &str = "preview";
+
+/// The suffix which can be added to a path to ask for the preview page of a redirect.
+const PREVIEW_SUFFIX: char = '+';
+
+/// A request for the preview page of a redirect.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PreviewRequest<'a> {
+    /// The path of the redirect, without the preview suffix.
+    pub path: &'a str,
+    /// The query string of the request, without the preview parameter.
+    pub query: Option<String>,
+}
+
+/// Determine whether a request is for the preview page of a redirect, either because its path
+/// ends in `+` or because its query string contains a `preview` parameter. A path ending in `+`
+/// which `is_alias` reports to be an alias in its own right is redirected as usual.
+pub fn preview_request<'a>(
+    path: &'a str,
+    query: Option<&str>,
+    is_alias: impl FnOnce(&str) -> bool,
+) -> Option<PreviewRequest<'a>> {
+    if let Some(stripped) = path.strip_suffix(PREVIEW_SUFFIX) {
+        if !is_alias(path) {
+            return Some(PreviewRequest {
+                path: stripped,
+                query: query.map(String::from),
+            });
+        }
+    }
+
+    let query = query?;
+    let pairs: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    if !pairs.iter().any(|(key, _)| key == PREVIEW_PARAM) {
+        return None;
+    }
+
+    let remaining: Vec<_> = pairs
+        .iter()
+        .filter(|(key, _)| key != PREVIEW_PARAM)
+        .collect();
+    let query = (!remaining.is_empty()).then(|| {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(remaining)
+            .finish()
+    });
+    Some(PreviewRequest { path, query })
+}
+
+/// Render the HTML preview page for a redirect, which describes where the redirect leads without
+/// following it, and carries Open Graph tags so that chat apps can unfurl links to it.
+pub fn render_preview(
+    short_url: &str,
+    redirect: &RedirectMatch,
+    location: &str,
+    status: StatusCode,
+    policy: QueryPolicy,
+) -> String {
+    let mut details = vec![
+        ("Alias", redirect.alias.to_string()),
+        (
+            "Status",
+            format!(
+                "{} {}",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or_default()
+            ),
+        ),
+        ("Query string", policy.to_string()),
+    ];
+    for target in &redirect.redirect.targets {
+        details.push(("Conditional target", target.url.clone()));
+    }
+    for variant in &redirect.redirect.variants {
+        let text = format!(
+            "{} (weight {}): {}",
+            variant.label, variant.weight, variant.url
+        );
+        details.push(("Variant", text));
+    }
+
+    let metadata = &redirect.redirect.metadata;
+    if let Some(description) = &metadata.description {
+        details.push(("Description", description.clone()));
+    }
+    if !metadata.tags.is_empty() {
+        details.push(("Tags", metadata.tags.join(", ")));
+    }
+    if let Some(owner) = &metadata.owner {
+        details.push(("Owner", owner.clone()));
+    }
+    for (term, timestamp) in [("Created", metadata.created), ("Updated", metadata.updated)] {
+        if let Some(timestamp) = timestamp.and_then(|t| t.format(&Rfc3339).ok()) {
+            details.push((term, timestamp));
+        }
+    }
+
+    let details: String = details
+        .iter()
+        .map(|(term, description)| {
+            format!(
+                "      <dt>{}</dt><dd>{}</dd>\n",
+                escape_html(term),
+                escape_html(description)
+            )
+        })
+        .collect();
+
+    // Only link to web pages and local paths, rather than any other scheme a URL may use.
+    let linkable = ["http://", "https://", "/"]
+        .iter()
+        .any(|prefix| location.starts_with(prefix));
+    let summary = match &metadata.description {
+        Some(description) => escape_html(description),
+        None => format!("Redirects to {}", escape_html(location)),
+    };
+    let short_url = escape_html(short_url);
+    let location = escape_html(location);
+    let target = if linkable {
+        format!(r#"<a href="{location}">{location}</a>"#)
+    } else {
+        location.clone()
+    };
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <meta name="robots" content="noindex">
+    <title>{short_url} &rarr; {location}</title>
+    <meta property="og:type" content="website">
+    <meta property="og:title" content="{short_url}">
+    <meta property="og:description" content="{summary}">
+    <meta property="og:url" content="{short_url}">
+    <style>
+      body {{ font-family: sans-serif; max-width: 40rem; margin: 2rem auto; padding: 0 1rem; }}
+      a {{ word-break: break-all; }}
+      dt {{ font-weight: bold; margin-top: 0.5rem; }}
+      dd {{ margin-left: 0; word-break: break-all; }}
+    </style>
+  </head>
+  <body>
+    <h1>{short_url}</h1>
+    <p>Redirects to {target}</p>
+    <dl>
+{details}    </dl>
+  </body>
+</html>
+"#
+    )
+}
+
+/// Escape text for inclusion in HTML, either as element content or as a quoted attribute value.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metadata::Metadata, redirects::Redirect};
+    use rstest::rstest;
+    use time::OffsetDateTime;
+
+    #[rstest]
+    #[case("foo", None, None)]
+    #[case("foo", Some("a=1"), None)]
+    #[case("foo+", None, Some(("foo", None)))]
+    #[case("foo+", Some("a=1"), Some(("foo", Some("a=1"))))]
+    #[case("foo", Some("preview"), Some(("foo", None)))]
+    #[case("foo", Some("a=1&preview=&b=2"), Some(("foo", Some("a=1&b=2"))))]
+    #[case("c++", None, None)]
+    #[case("c++", Some("preview"), Some(("c++", None)))]
+    #[case("c+++", None, Some(("c++", None)))]
+    fn test_preview_request(
+        #[case] path: &str,
+        #[case] query: Option<&str>,
+        #[case] expected: Option<(&str, Option<&str>)>,
+    ) {
+        let expected = expected.map(|(path, query)| PreviewRequest {
+            path,
+            query: query.map(String::from),
+        });
+        assert_eq!(preview_request(path, query, |p| p == "c++"), expected);
+    }
+
+    #[test]
+    fn test_render_preview() {
+        let redirect = Redirect::new("https://example.com/?a=1&b=<2>");
+        let redirect_match = RedirectMatch {
+            alias: "foo",
+            redirect: &redirect,
+            url: redirect.url.clone(),
+            variant: None,
+        };
+
+        let page = render_preview(
+            "https://go.example/foo",
+            &redirect_match,
+            &redirect.url,
+            StatusCode::FOUND,
+            QueryPolicy::Merge,
+        );
+
+        assert!(page.contains(r#"<meta property="og:url" content="https://go.example/foo">"#));
+        assert!(page.contains(
+            r#"<meta property="og:description" content="Redirects to https://example.com/?a=1&amp;b=&lt;2&gt;">"#
+        ));
+        assert!(page.contains(r#"<a href="https://example.com/?a=1&amp;b=&lt;2&gt;">"#));
+        assert!(page.contains("<dt>Status</dt><dd>302 Found</dd>"));
+        assert!(page.contains("<dt>Query string</dt><dd>merge</dd>"));
+    }
+
+    #[test]
+    fn test_render_preview_metadata() {
+        let mut redirect = Redirect::new("https://example.com/handbook");
+        redirect.metadata = Metadata {
+            description: Some("The team <handbook>".to_string()),
+            tags: vec!["docs".to_string(), "onboarding".to_string()],
+            owner: Some("platform".to_string()),
+            created: Some(OffsetDateTime::from_unix_timestamp(1704164645).unwrap()),
+            updated: None,
+        };
+        let redirect_match = RedirectMatch {
+            alias: "handbook",
+            redirect: &redirect,
+            url: redirect.url.clone(),
+            variant: None,
+        };
+
+        let page = render_preview(
+            "https://go.example/handbook",
+            &redirect_match,
+            &redirect.url,
+            StatusCode::FOUND,
+            QueryPolicy::Drop,
+        );
+
+        assert!(page
+            .contains(r#"<meta property="og:description" content="The team &lt;handbook&gt;">"#));
+        assert!(page.contains("<dt>Description</dt><dd>The team &lt;handbook&gt;</dd>"));
+        assert!(page.contains("<dt>Tags</dt><dd>docs, onboarding</dd>"));
+        assert!(page.contains("<dt>Owner</dt><dd>platform</dd>"));
+        assert!(page.contains("<dt>Created</dt><dd>2024-01-02T03:04:05Z</dd>"));
+        assert!(!page.contains("<dt>Updated</dt>"));
+    }
+
+    #[test]
+    fn test_render_preview_unlinkable() {
+        let redirect = Redirect::new("javascript:alert(1)");
+        let redirect_match = RedirectMatch {
+            alias: "foo",
+            redirect: &redirect,
+            url: redirect.url.clone(),
+            variant: None,
+        };
+
+        let page = render_preview(
+            "https://go.example/foo",
+            &redirect_match,
+            &redirect.url,
+            StatusCode::FOUND,
+            QueryPolicy::Drop,
+        );
+        assert!(page.contains("<p>Redirects to javascript:alert(1)</p>"));
+        assert!(!page.contains("<a href"));
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+}
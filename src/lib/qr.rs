@@ -0,0 +1,31 @@
+use anyhow::{Context, Result};
+use qrcode::{render::svg, QrCode};
+
+/// The smallest width and height of a rendered QR code, in pixels.
+const QR_MIN_DIMENSIONS: u32 = 256;
+
+/// Render a QR code encoding the given data, such as a short URL, as an SVG image.
+pub fn render_qr_svg(data: &str) -> Result<String> {
+    let code = QrCode::new(data.as_bytes()).context("failed to encode QR code")?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(QR_MIN_DIMENSIONS, QR_MIN_DIMENSIONS)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_qr_svg() {
+        let svg = render_qr_svg("https://go.example/github").unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<svg"));
+    }
+
+    #[test]
+    fn test_render_qr_svg_too_long() {
+        assert!(render_qr_svg(&"a".repeat(8000)).is_err());
+    }
+}
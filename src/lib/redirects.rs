@@ -1,42 +1,954 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, path::Path, str::FromStr};
 
+use anyhow::{bail, Context, Result};
+use axum::http::{header, HeaderMap, StatusCode};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use tracing::warn;
-use url::Url;
-pub type Redirects = HashMap<String, String>;
+use url::{Position, Url};
+
+use crate::{
+    conditions::ConditionalTarget,
+    diagnostics::{DiagnosticKind, ParseReport},
+    handlers::is_asset,
+    metadata::{Metadata, RawTimestamp},
+    variants::{choose_variant, validate_variants, WeightedVariant},
+};
+
+/// The suffix used on an alias to declare a prefix redirect, which matches any path beneath it.
+const WILDCARD_SUFFIX: &str = "/*";
+
+/// The characters which are percent-encoded when text from a request path is placed in a redirect
+/// URL. Request paths are matched after they are decoded, so without this, an encoded `?` or `#`
+/// would start a query string or fragment, and an encoded newline would make the URL invalid.
+const FORWARDED_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// The prefix used on an alias to declare a regex redirect rule.
+const REGEX_PREFIX: &str = "^";
+
+/// The separator which may optionally be placed between a regex rule and its URL in the
+/// line-based format.
+const REGEX_SEPARATOR: &str = "->";
+
+/// A base URL against which the paths of local aliases are resolved when applying a query policy.
+const LOCAL_BASE_URL: &str = "http://localhost";
+
+/// The set of redirects defined for a Servy instance. Redirects can either be scoped to the
+/// `Host` of the request, or be defined in a fallback table which applies to any host.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Redirects {
+    /// The redirects which apply to any host.
+    default: RedirectTable,
+    /// The redirects scoped to particular hosts, keyed by lowercase hostname.
+    hosts: HashMap<String, RedirectTable>,
+}
+
+/// The result of resolving a request path against the defined redirects.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RedirectMatch<'a> {
+    /// The alias of the redirect that matched.
+    pub alias: &'a str,
+    pub redirect: &'a Redirect,
+    /// The URL to redirect to, with any forwarded path substituted in.
+    pub url: String,
+    /// The label of the weighted variant chosen for the request, if any.
+    pub variant: Option<&'a str>,
+}
+
+impl Redirects {
+    /// Construct an empty set of redirects.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a redirect for a given alias which applies to any host, replacing any existing
+    /// redirect for that alias. Returns an error if the alias is a regex rule which cannot be
+    /// compiled.
+    pub fn insert(&mut self, alias: &str, redirect: Redirect) -> Result<()> {
+        self.default.insert(alias, redirect)
+    }
+
+    /// Insert a redirect for a given alias which only applies to requests for the given host.
+    pub fn insert_for_host(&mut self, host: &str, alias: &str, redirect: Redirect) -> Result<()> {
+        self.hosts
+            .entry(normalize_host(host))
+            .or_default()
+            .insert(alias, redirect)
+    }
+
+    /// Return the redirect defined for exactly the given alias in the fallback table.
+    pub fn get(&self, alias: &str) -> Option<&Redirect> {
+        self.default.entries.get(alias)
+    }
+
+    /// Return the redirect defined for exactly the given alias for a given host.
+    pub fn get_for_host(&self, host: &str, alias: &str) -> Option<&Redirect> {
+        self.hosts.get(&normalize_host(host))?.entries.get(alias)
+    }
+
+    /// Return the number of redirects defined, across all hosts.
+    pub fn len(&self) -> usize {
+        self.default.entries.len() + self.hosts.values().map(|t| t.entries.len()).sum::<usize>()
+    }
+
+    /// Report whether there are no redirects defined.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the defined redirects, yielding the host each is scoped to (if any), the
+    /// alias and the redirect.
+    pub fn iter(&self) -> impl Iterator<Item = (Option<&str>, &str, &Redirect)> {
+        let default = self
+            .default
+            .entries
+            .iter()
+            .map(|(a, r)| (None, a.as_str(), r));
+        let hosts = self.hosts.iter().flat_map(|(host, table)| {
+            table
+                .entries
+                .iter()
+                .map(move |(a, r)| (Some(host.as_str()), a.as_str(), r))
+        });
+        default.chain(hosts)
+    }
+
+    /// Merge another set of redirects into this one. Where an alias is defined in both for the
+    /// same host, the existing redirect is kept. Returns the host (if any) and alias of each
+    /// redirect which was not merged because of such a conflict, sorted by alias.
+    pub fn merge(&mut self, other: Redirects) -> Vec<(Option<String>, String)> {
+        let mut conflicts: Vec<_> = self
+            .default
+            .merge(other.default)
+            .into_iter()
+            .map(|alias| (None, alias))
+            .collect();
+
+        for (host, table) in other.hosts {
+            let merged = self.hosts.entry(host.clone()).or_default().merge(table);
+            conflicts.extend(merged.into_iter().map(|alias| (Some(host.clone()), alias)));
+        }
+
+        conflicts.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+        conflicts
+    }
+
+    /// Resolve a request path to a redirect, given the headers of the request. If the request's
+    /// `Host` has its own table of redirects, it is searched first, falling back to the redirects
+    /// which apply to any host.
+    ///
+    /// Within a table, exact aliases are tried first (ignoring any trailing slash), then prefix
+    /// redirects from longest to shortest, then regex rules in the order they were declared.
+    /// When a prefix redirect matches, the remainder of the path replaces a trailing `*` in its
+    /// URL. When a regex rule matches, `$1`-style references in its URL are replaced with the
+    /// corresponding capture groups.
+    pub fn lookup(&self, path: &str, headers: &HeaderMap) -> Option<RedirectMatch<'_>> {
+        headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .and_then(|host| self.hosts.get(&normalize_host(host)))
+            .and_then(|table| table.lookup(path, headers))
+            .or_else(|| self.default.lookup(path, headers))
+    }
+
+    /// Report whether a request path matches an exact alias, either one scoped to the request's
+    /// host or one which applies to any host.
+    pub fn has_exact(&self, path: &str, headers: &HeaderMap) -> bool {
+        headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .and_then(|host| self.hosts.get(&normalize_host(host)))
+            .and_then(|table| table.lookup_exact(path, headers))
+            .or_else(|| self.default.lookup_exact(path, headers))
+            .is_some()
+    }
+
+    /// Resolve a request path to a redirect as [`Redirects::lookup`] does, then follow any chain
+    /// of redirects whose URL is the path of another local alias (such as `/github`), for at most
+    /// `max_depth` hops. The returned match is for the redirect at the end of the chain. Returns
+    /// `None` if the chain is longer than `max_depth`.
+    pub fn resolve(
+        &self,
+        path: &str,
+        headers: &HeaderMap,
+        max_depth: usize,
+    ) -> Option<RedirectMatch<'_>> {
+        let mut resolved = self.lookup(path, headers)?;
+
+        let mut hops = 0;
+        while let Some(next) = local_alias_path(&resolved.url)
+            .map(|p| percent_decode_str(p).decode_utf8_lossy())
+            .and_then(|p| self.lookup(&p, headers))
+        {
+            if hops == max_depth {
+                warn!("alias chain from '{path}' is longer than {max_depth} hops");
+                return None;
+            }
+            hops += 1;
+            resolved = next;
+        }
+
+        Some(resolved)
+    }
+
+    /// Remove any redirects which point at other local aliases in a cycle, or through a chain of
+    /// more than `max_depth` aliases, recording the aliases involved in the report. Only exact
+    /// aliases are followed, as the targets of prefix and regex redirects depend on the request.
+    /// Returns the number of redirects removed.
+    pub fn reject_invalid_chains(&mut self, max_depth: usize, report: &mut ParseReport) -> usize {
+        let mut rejected = Vec::new();
+
+        for (host, table) in std::iter::once((None, &self.default))
+            .chain(self.hosts.iter().map(|(h, t)| (Some(h.as_str()), t)))
+        {
+            for alias in table.entries.keys() {
+                let Some(chain) = self.invalid_chain(host, alias, max_depth) else {
+                    continue;
+                };
+                report.push_for_alias(host, alias, DiagnosticKind::AliasChain, &chain);
+                rejected.push((host.map(String::from), alias.clone()));
+            }
+        }
+
+        for (host, alias) in &rejected {
+            let table = match host {
+                Some(host) => self.hosts.get_mut(host),
+                None => Some(&mut self.default),
+            };
+            if let Some(table) = table {
+                table.remove(alias);
+            }
+        }
+
+        rejected.len()
+    }
+
+    /// Follow the chain of local aliases starting at a given alias, returning a description of
+    /// the chain if it contains a cycle or is longer than `max_depth`.
+    fn invalid_chain(&self, host: Option<&str>, alias: &str, max_depth: usize) -> Option<String> {
+        let table = host.and_then(|h| self.hosts.get(h));
+        let mut chain = vec![alias];
+
+        loop {
+            let current = chain.last()?;
+            let redirect = table
+                .and_then(|t| t.entries.get(*current))
+                .or_else(|| self.default.entries.get(*current))?;
+            let next = local_alias_path(&redirect.url)?;
+            let next = next.strip_suffix('/').unwrap_or(next);
+
+            let (next, _) = table
+                .and_then(|t| t.entries.get_key_value(next))
+                .or_else(|| self.default.entries.get_key_value(next))?;
+
+            let cycle = chain.contains(&next.as_str());
+            chain.push(next);
+            if cycle {
+                return Some(format!("alias cycle detected: {}", chain.join(" -> ")));
+            }
+            if chain.len() - 1 > max_depth {
+                return Some(format!(
+                    "alias chain is longer than {max_depth} hops: {}",
+                    chain.join(" -> ")
+                ));
+            }
+        }
+    }
+}
+
+/// Return the path of the local alias a redirect URL points at, without its leading slash, if
+/// the URL is a local path such as `/github` rather than an absolute URL.
+fn local_alias_path(url: &str) -> Option<&str> {
+    let path = url.strip_prefix('/').filter(|p| !p.starts_with('/'))?;
+    path.split(['?', '#']).next()
+}
+
+/// Percent-encode text taken from a decoded request path, so that it can be placed in a URL
+/// without changing the structure of the URL. Slashes are kept, so that paths are forwarded as
+/// they are.
+pub(crate) fn encode_path(text: &str) -> String {
+    utf8_percent_encode(text, FORWARDED_PATH).to_string()
+}
+
+/// Expand the target of a regex rule, replacing references to capture groups (`$1`, `${1}`,
+/// `$name` or `${name}`) with the text they captured, percent-encoded in the same way as forwarded
+/// paths. `$$` is replaced with a literal `$`, and references to groups which did not match are
+/// removed, as with [`Captures::expand`].
+fn expand_encoded(captures: &Captures, target: &str) -> String {
+    let mut url = String::with_capacity(target.len());
+    let mut rest = target;
+
+    while let Some(i) = rest.find('$') {
+        url.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            url.push('$');
+            rest = after;
+            continue;
+        }
+
+        let (name, after) = match rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+            Some((name, after)) => (name, after),
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+        if name.is_empty() {
+            url.push('$');
+            continue;
+        }
+
+        let group = match name.parse::<usize>() {
+            Ok(index) => captures.get(index),
+            Err(_) => captures.name(name),
+        };
+        if let Some(group) = group {
+            url.push_str(&encode_path(group.as_str()));
+        }
+        rest = after;
+    }
+
+    url.push_str(rest);
+    url
+}
+
+/// Report whether a redirect URL is valid: either an absolute URL, or the path of a local alias.
+fn is_valid_url(url: &str) -> bool {
+    local_alias_path(url).is_some() || Url::parse(url).is_ok()
+}
+
+/// Normalise the value of a `Host` header for comparison, lowercasing it and removing any port.
+fn normalize_host(host: &str) -> String {
+    let host = host.trim().to_lowercase();
+    match host.strip_prefix('[') {
+        // IPv6 addresses are enclosed in brackets, and may contain colons.
+        Some(rest) => match rest.split_once(']') {
+            Some((addr, _)) => format!("[{addr}]"),
+            None => host,
+        },
+        None => host.split(':').next().unwrap_or_default().to_string(),
+    }
+}
+
+/// A table of redirects keyed by alias. Aliases ending in `/*` are prefix redirects, which match
+/// any path beneath the prefix. Aliases beginning with `^` are regex rules, which are matched
+/// against the full request path including its leading slash.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct RedirectTable {
+    entries: HashMap<String, Redirect>,
+    /// The prefix redirect aliases, ordered from longest to shortest.
+    prefixes: Vec<String>,
+    /// The compiled regex rules, in the order they were declared.
+    rules: Vec<RegexRule>,
+}
+
+/// A compiled regex redirect rule.
+#[derive(Clone, Debug)]
+struct RegexRule {
+    alias: String,
+    regex: Regex,
+}
+
+impl PartialEq for RegexRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.alias == other.alias
+    }
+}
+
+impl Eq for RegexRule {}
+
+impl RedirectTable {
+    /// Insert a redirect for a given alias, replacing any existing redirect for that alias.
+    fn insert(&mut self, alias: &str, redirect: Redirect) -> Result<()> {
+        if !self.entries.contains_key(alias) {
+            if alias.starts_with(REGEX_PREFIX) {
+                let regex = Regex::new(alias)
+                    .with_context(|| format!("invalid regex redirect rule: '{alias}'"))?;
+                self.rules.push(RegexRule {
+                    alias: alias.to_string(),
+                    regex,
+                });
+            } else if alias.ends_with(WILDCARD_SUFFIX) {
+                self.prefixes.push(alias.to_string());
+                self.prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+            }
+        }
+        self.entries.insert(alias.to_string(), redirect);
+        Ok(())
+    }
+
+    /// Merge another table into this one, keeping the existing redirect for any alias defined in
+    /// both. Regex rules from the other table are tried after those already in this one. Returns
+    /// the aliases which were not merged.
+    fn merge(&mut self, other: RedirectTable) -> Vec<String> {
+        for rule in other.rules {
+            if !self.entries.contains_key(&rule.alias) {
+                self.rules.push(rule);
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        for (alias, redirect) in other.entries {
+            if self.entries.contains_key(&alias) {
+                conflicts.push(alias);
+                continue;
+            }
+            if !alias.starts_with(REGEX_PREFIX) && alias.ends_with(WILDCARD_SUFFIX) {
+                self.prefixes.push(alias.clone());
+            }
+            self.entries.insert(alias, redirect);
+        }
+        self.prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+
+        conflicts
+    }
+
+    /// Remove the redirect for a given alias.
+    fn remove(&mut self, alias: &str) {
+        self.entries.remove(alias);
+        self.prefixes.retain(|p| p != alias);
+        self.rules.retain(|r| r.alias != alias);
+    }
+
+    /// Resolve a request path to a redirect in this table.
+    fn lookup(&self, path: &str, headers: &HeaderMap) -> Option<RedirectMatch<'_>> {
+        self.lookup_exact(path, headers)
+            .or_else(|| self.lookup_prefix(path, headers))
+            .or_else(|| self.lookup_regex(path, headers))
+    }
+
+    /// Resolve a request path against the exact aliases.
+    fn lookup_exact(&self, path: &str, headers: &HeaderMap) -> Option<RedirectMatch<'_>> {
+        let key = path.strip_suffix("/").unwrap_or(path);
+        if key.starts_with(REGEX_PREFIX) || key.ends_with(WILDCARD_SUFFIX) {
+            return None;
+        }
+
+        let (alias, redirect) = self.entries.get_key_value(key)?;
+        let (target, variant) = redirect.target_for(alias, headers);
+        Some(RedirectMatch {
+            alias,
+            redirect,
+            url: target.to_string(),
+            variant,
+        })
+    }
+
+    /// Resolve a request path against the prefix redirects.
+    fn lookup_prefix(&self, path: &str, headers: &HeaderMap) -> Option<RedirectMatch<'_>> {
+        self.prefixes.iter().find_map(|alias| {
+            let prefix = alias.strip_suffix('*')?;
+            let rest = match path.strip_prefix(prefix) {
+                Some(rest) => rest,
+                None if Some(path) == prefix.strip_suffix('/') => "",
+                None => return None,
+            };
+
+            let redirect = self.entries.get(alias)?;
+            let (target, variant) = redirect.target_for(alias, headers);
+            let url = match target.strip_suffix('*') {
+                Some(base) => format!("{base}{}", encode_path(rest)),
+                None => target.to_string(),
+            };
+
+            Some(RedirectMatch {
+                alias,
+                redirect,
+                url,
+                variant,
+            })
+        })
+    }
+
+    /// Resolve a request path against the regex rules.
+    fn lookup_regex(&self, path: &str, headers: &HeaderMap) -> Option<RedirectMatch<'_>> {
+        let path = format!("/{path}");
+        self.rules.iter().find_map(|rule| {
+            let captures = rule.regex.captures(&path)?;
+            let redirect = self.entries.get(&rule.alias)?;
+
+            let (target, variant) = redirect.target_for(&rule.alias, headers);
+            let url = expand_encoded(&captures, target);
+
+            Some(RedirectMatch {
+                alias: &rule.alias,
+                redirect,
+                url,
+                variant,
+            })
+        })
+    }
+}
+
+/// A single redirect target, along with the options that control how it is served.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    pub url: String,
+    /// The status code to redirect with. If unset, the configured default is used.
+    pub status: Option<StatusCode>,
+    /// How the query string of the request is handled. If unset, the configured default is used.
+    pub query: Option<QueryPolicy>,
+    /// Alternative targets, chosen according to attributes of the request. The first target
+    /// whose conditions match is used, falling back to `url` if none match.
+    pub targets: Vec<ConditionalTarget>,
+    /// Weighted destinations which split the traffic that is not matched by `targets`. A visitor
+    /// is assigned a variant at random, and kept on it by a cookie.
+    pub variants: Vec<WeightedVariant>,
+    /// Information which describes the redirect, without changing how it is served.
+    pub metadata: Metadata,
+}
+
+impl Redirect {
+    /// Construct a new Redirect to a given URL, using the default status code and query policy.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            status: None,
+            query: None,
+            targets: Vec::new(),
+            variants: Vec::new(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Return the URL to redirect to for a request with the given headers, along with the label
+    /// of the weighted variant that was chosen, if any. Conditional targets take precedence over
+    /// weighted variants, and `url` is used if neither applies.
+    pub fn target_for(&self, alias: &str, headers: &HeaderMap) -> (&str, Option<&str>) {
+        if let Some(target) = self.targets.iter().find(|t| t.matches(headers)) {
+            return (&target.url, None);
+        }
+
+        match choose_variant(&self.variants, alias, headers) {
+            Some(variant) => (&variant.url, Some(&variant.label)),
+            None => (&self.url, None),
+        }
+    }
+
+    /// Iterate over every URL the redirect may send a request to.
+    fn urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str())
+            .chain(self.targets.iter().map(|t| t.url.as_str()))
+            .chain(self.variants.iter().map(|v| v.url.as_str()))
+    }
+}
+
+/// Determines how the query string of an incoming request is applied to a redirect's URL.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryPolicy {
+    /// Discard the incoming query string, redirecting to the URL as specified.
+    #[default]
+    Drop,
+    /// Replace any query string on the URL with the incoming query string.
+    Pass,
+    /// Combine the incoming query string with any query string on the URL. Where both specify
+    /// the same parameter, the incoming value wins.
+    Merge,
+}
+
+impl QueryPolicy {
+    /// Apply the policy to a redirect URL, given the query string of the incoming request.
+    pub fn apply(&self, url: &str, query: Option<&str>) -> String {
+        let query = match query {
+            Some(query) if !query.is_empty() && *self != QueryPolicy::Drop => query,
+            _ => return url.to_string(),
+        };
+
+        // Local paths are resolved against a placeholder base, which is removed again below.
+        let local = local_alias_path(url).is_some();
+        let parsed = if local {
+            Url::parse(LOCAL_BASE_URL).and_then(|base| base.join(url))
+        } else {
+            Url::parse(url)
+        };
+        let Ok(mut target) = parsed else {
+            return url.to_string();
+        };
+
+        if *self == QueryPolicy::Merge {
+            let incoming: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect();
+            let existing: Vec<(String, String)> = target
+                .query_pairs()
+                .into_owned()
+                .filter(|(k, _)| !incoming.iter().any(|(i, _)| i == k))
+                .collect();
+
+            target
+                .query_pairs_mut()
+                .clear()
+                .extend_pairs(existing)
+                .extend_pairs(incoming);
+        } else {
+            target.set_query(Some(query));
+        }
+
+        if local {
+            target[Position::BeforePath..].to_string()
+        } else {
+            target.to_string()
+        }
+    }
+}
+
+impl fmt::Display for QueryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            QueryPolicy::Drop => "drop",
+            QueryPolicy::Pass => "pass",
+            QueryPolicy::Merge => "merge",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for QueryPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "drop" => Ok(QueryPolicy::Drop),
+            "pass" => Ok(QueryPolicy::Pass),
+            "merge" => Ok(QueryPolicy::Merge),
+            _ => bail!("invalid query policy: {s}"),
+        }
+    }
+}
+
+/// Validate that a numeric status code is one that can be used for a redirect.
+pub fn redirect_status(code: u16) -> Result<StatusCode> {
+    match code {
+        301 | 302 | 303 | 307 | 308 => Ok(StatusCode::from_u16(code)?),
+        _ => bail!("invalid redirect status code: {code}"),
+    }
+}
+
+/// The formats in which a redirects list can be written. The space-separated [`Format::Lines`]
+/// format is the default, and is compatible with gosherve.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Lines,
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// Determine the format of a redirects list from a Content-Type header value.
+    pub fn from_content_type(content_type: &str) -> Option<Format> {
+        let mime = content_type.split(';').next()?.trim().to_lowercase();
+        match mime.as_str() {
+            "application/json" => Some(Format::Json),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                Some(Format::Yaml)
+            }
+            "application/toml" | "text/toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    /// Determine the format of a redirects list from the extension of a file path or URL path.
+    pub fn from_path(path: &str) -> Option<Format> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// The structure of a redirects list written in one of the structured formats.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedirectsDocument {
+    #[serde(default)]
+    pub redirects: Vec<RedirectEntry>,
+}
+
+/// A single entry in a structured redirects list.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedirectEntry {
+    /// The host the redirect is scoped to. If unset, the redirect applies to any host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub alias: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<QueryPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<ConditionalTarget>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<WeightedVariant>,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+}
+
+impl RedirectEntry {
+    /// Construct a new entry for an alias which redirects to a given URL, using the default
+    /// options.
+    pub fn new(alias: &str, url: &str) -> Self {
+        Self {
+            host: None,
+            alias: alias.to_string(),
+            url: url.to_string(),
+            status: None,
+            query: None,
+            targets: Vec::new(),
+            variants: Vec::new(),
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+/// The structure of a redirects list as it is deserialised for loading, before its entries have
+/// been validated.
+#[derive(Deserialize)]
+struct RawDocument {
+    #[serde(default)]
+    redirects: Vec<RawEntry>,
+}
+
+/// An entry in a structured redirects list whose timestamps have not been parsed yet, so that an
+/// invalid timestamp only affects the entry it belongs to.
+#[derive(Deserialize)]
+struct RawEntry {
+    created: Option<RawTimestamp>,
+    updated: Option<RawTimestamp>,
+    #[serde(flatten)]
+    entry: RedirectEntry,
+}
+
+impl RawEntry {
+    /// Parse the timestamps of the entry, returning the entry with them set.
+    fn into_entry(self) -> Result<RedirectEntry> {
+        let mut entry = self.entry;
+        entry.metadata.created = self.created.map(RawTimestamp::parse).transpose()?;
+        entry.metadata.updated = self.updated.map(RawTimestamp::parse).transpose()?;
+        Ok(entry)
+    }
+}
+
+/// Parse the contents of a redirects list written in the given format, returning the redirects
+/// along with a report of any problems found. Errors are only returned if a structured document
+/// cannot be deserialised; invalid entries are skipped and recorded in the report.
+pub fn parse_document(contents: &str, format: Format) -> Result<(Redirects, ParseReport)> {
+    let document: RawDocument = match format {
+        Format::Lines => return Ok(parse_redirects(contents)),
+        Format::Json => serde_json::from_str(contents).context("invalid JSON redirects list")?,
+        Format::Yaml => serde_yaml::from_str(contents).context("invalid YAML redirects list")?,
+        Format::Toml => toml::from_str(contents).context("invalid TOML redirects list")?,
+    };
+
+    let mut parser = Parser::default();
+    for (i, entry) in document.redirects.into_iter().enumerate() {
+        let line = i + 1;
+        let alias = entry.entry.alias.clone();
+        let entry = match entry.into_entry() {
+            Ok(entry) => entry,
+            Err(e) => {
+                let message = e.to_string();
+                parser.report(line, &alias, DiagnosticKind::InvalidOption, &message);
+                continue;
+            }
+        };
+        let status = match entry.status.map(redirect_status).transpose() {
+            Ok(status) => status,
+            Err(e) => {
+                let message = e.to_string();
+                parser.report(line, &entry.alias, DiagnosticKind::InvalidOption, &message);
+                continue;
+            }
+        };
+        if let Err(e) = validate_variants(&entry.variants) {
+            let message = e.to_string();
+            parser.report(line, &entry.alias, DiagnosticKind::InvalidOption, &message);
+            continue;
+        }
+
+        let redirect = Redirect {
+            url: entry.url,
+            status,
+            query: entry.query,
+            targets: entry.targets,
+            variants: entry.variants,
+            metadata: entry.metadata,
+        };
+        let host = entry.host.as_deref();
+        parser.insert(line, &entry.alias, host, &entry.alias, redirect);
+    }
+
+    Ok(parser.finish())
+}
 
 /// Parse the contents of a redirects file (usually fetched from the internet), returning
-/// a HashMap that maps redirect aliases -> URLs.
-pub fn parse_redirects(contents: &str) -> Redirects {
-    let mut map = HashMap::new();
+/// a map of redirect aliases -> URLs along with a report of any problems found. Each line is an
+/// alias and a URL separated by a single space, optionally preceded by a host to scope the
+/// redirect to, and optionally followed by `key=value` options such as `status=302` or
+/// `query=merge`.
+pub fn parse_redirects(contents: &str) -> (Redirects, ParseReport) {
+    let mut parser = Parser::default();
+
+    contents.lines().enumerate().for_each(|(i, l)| {
+        let line = i + 1;
 
-    contents.lines().for_each(|l| {
         // Ignore empty lines, and lines beginning with a '#'.
         if l.starts_with("#") || l.is_empty() {
             return;
         }
 
-        let parts: Vec<&str> = l.split(" ").collect();
-        // Ignore lines with more than one space.
-        if parts.len() != 2 {
-            warn!("invalid redirect specification: '{}'", l);
-            return;
+        let mut parts: Vec<&str> = l.split(" ").collect();
+
+        // Regex rules may separate the pattern from the URL with an arrow.
+        if let Some(i) = parts.iter().position(|p| p.starts_with(REGEX_PREFIX)) {
+            if parts.get(i + 1) == Some(&REGEX_SEPARATOR) {
+                parts.remove(i + 1);
+            }
         }
 
-        // Check the URL for a given key is actually a valid URL.
-        if Url::parse(parts[1]).is_ok() {
-            map.insert(parts[0].to_string(), parts[1].to_string());
-        } else {
-            warn!("invalid url detected in redirects file: '{}'", parts[1]);
+        // Ignore lines without an alias and a URL, optionally preceded by a host.
+        let positional = parts.iter().take_while(|p| !is_line_option(p)).count();
+        let (host, alias, url) = match parts[..positional] {
+            [alias, url] => (None, alias, url),
+            [host, alias, url] => (Some(host), alias, url),
+            _ => {
+                let message = "expected an alias and a URL, optionally preceded by a host";
+                parser.report(line, l, DiagnosticKind::FieldCount, message);
+                return;
+            }
+        };
+
+        let mut redirect = Redirect::new(url);
+        if let Err(e) = parse_line_options(&mut redirect, &parts[positional..]) {
+            let message = format!("invalid option: {e}");
+            parser.report(line, l, DiagnosticKind::InvalidOption, &message);
+            return;
         }
+
+        parser.insert(line, l, host, alias, redirect);
     });
 
-    map
+    parser.finish()
+}
+
+/// Report whether an alias matches a single path, rather than being a prefix or regex redirect.
+pub(crate) fn is_exact_alias(alias: &str) -> bool {
+    !alias.starts_with(REGEX_PREFIX) && !alias.ends_with(WILDCARD_SUFFIX)
+}
+
+/// Report whether a token in a line-based redirect specification is a `key=value` option.
+/// URLs may also contain an '=', but never in a position where the part before it is a
+/// plain identifier.
+fn is_line_option(token: &str) -> bool {
+    token.split_once('=').is_some_and(|(key, _)| {
+        !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Parse the `key=value` options which may follow the URL in a line-based redirect
+/// specification, applying them to the given redirect.
+fn parse_line_options(redirect: &mut Redirect, options: &[&str]) -> Result<()> {
+    for option in options {
+        match option.split_once('=') {
+            Some(("status", code)) => redirect.status = Some(redirect_status(code.parse()?)?),
+            Some(("query", policy)) => redirect.query = Some(policy.parse()?),
+            _ => bail!("unknown option '{option}'"),
+        }
+    }
+    Ok(())
+}
+
+/// Collects the redirects parsed from a list, along with a report of any problems found.
+#[derive(Default)]
+struct Parser {
+    map: Redirects,
+    report: ParseReport,
+}
+
+impl Parser {
+    /// Record a problem with the entry at a given line.
+    fn report(&mut self, line: usize, text: &str, kind: DiagnosticKind, message: &str) {
+        self.report.push(line, text, kind, message);
+    }
+
+    /// Insert a redirect into the map, provided the URL for the given key is actually a valid
+    /// URL, or the path of another local alias. Duplicate aliases, and aliases which collide
+    /// with an embedded asset, are inserted but reported.
+    fn insert(
+        &mut self,
+        line: usize,
+        text: &str,
+        host: Option<&str>,
+        alias: &str,
+        redirect: Redirect,
+    ) {
+        if let Some(url) = redirect.urls().find(|url| !is_valid_url(url)) {
+            let message = format!("invalid url '{url}'");
+            self.report(line, text, DiagnosticKind::InvalidUrl, &message);
+            return;
+        }
+
+        let existing = match host {
+            Some(host) => self.map.get_for_host(host, alias),
+            None => self.map.get(alias),
+        };
+        if existing.is_some() {
+            let message = format!("duplicate alias '{alias}' replaces an earlier definition");
+            self.report(line, text, DiagnosticKind::DuplicateAlias, &message);
+        }
+
+        if is_exact_alias(alias) && is_asset(alias) {
+            let message = format!("alias '{alias}' is an embedded asset, which is served instead");
+            self.report(line, text, DiagnosticKind::AssetConflict, &message);
+        }
+
+        let result = match host {
+            Some(host) => self.map.insert_for_host(host, alias, redirect),
+            None => self.map.insert(alias, redirect),
+        };
+
+        match result {
+            Ok(()) => {
+                let host = host.map(normalize_host);
+                self.report.define(host.as_deref(), alias, line, text);
+            }
+            Err(e) => self.report(line, text, DiagnosticKind::InvalidRegex, &format!("{e:#}")),
+        }
+    }
+
+    /// Return the parsed redirects and the report of problems found.
+    fn finish(self) -> (Redirects, ParseReport) {
+        (self.map, self.report)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use rstest::rstest;
+    use serde_json::json;
 
     #[rstest]
     #[case(
@@ -67,13 +979,707 @@ mod tests {
         ]
     )]
     fn test_parse_redirects(#[case] input: Vec<&str>, #[case] expected: Vec<(&str, &str)>) {
-        let redirects = parse_redirects(&input.join("\n"));
+        let (redirects, _) = parse_redirects(&input.join("\n"));
 
         let mut expected_map = Redirects::new();
         for (key, value) in expected {
-            expected_map.insert(key.to_string(), value.to_string());
+            expected_map.insert(key, Redirect::new(value)).unwrap();
         }
 
         assert_eq!(expected_map, redirects);
     }
+
+    #[rstest]
+    #[case("foo http://foo.bar", Some(None))]
+    #[case("foo http://foo.bar status=302", Some(Some(StatusCode::FOUND)))]
+    #[case(
+        "foo http://foo.bar status=307",
+        Some(Some(StatusCode::TEMPORARY_REDIRECT))
+    )]
+    #[case("foo http://foo.bar status=200", None)]
+    #[case("foo http://foo.bar status=abc", None)]
+    #[case("foo http://foo.bar colour=blue", None)]
+    #[case("foo http://foo.bar 302", None)]
+    fn test_parse_redirects_status(
+        #[case] input: &str,
+        #[case] expected: Option<Option<StatusCode>>,
+    ) {
+        let (redirects, _) = parse_redirects(input);
+        assert_eq!(redirects.get("foo").map(|r| r.status), expected);
+    }
+
+    #[rstest]
+    #[case("foo", Some(("foo", "http://foo.bar")))]
+    #[case("foo/", Some(("foo", "http://foo.bar")))]
+    #[case("docs", Some(("docs/*", "https://docs.example.com/")))]
+    #[case("docs/", Some(("docs/*", "https://docs.example.com/")))]
+    #[case(
+        "docs/guide/install",
+        Some(("docs/*", "https://docs.example.com/guide/install"))
+    )]
+    #[case(
+        "docs/guide/",
+        Some(("docs/*", "https://docs.example.com/guide/"))
+    )]
+    #[case(
+        "docs/api/v1/users",
+        Some(("docs/api/*", "https://api.example.com/v1/users"))
+    )]
+    #[case("docs/exact", Some(("docs/exact", "http://exact.example")))]
+    #[case("old/anything/here", Some(("old/*", "https://example.com/moved")))]
+    #[case("documents", None)]
+    #[case("unknown", None)]
+    #[case("docs/a?b#c", Some(("docs/*", "https://docs.example.com/a%3Fb%23c")))]
+    #[case("docs/a\r\nX:y", Some(("docs/*", "https://docs.example.com/a%0D%0AX:y")))]
+    #[case("docs/100%/a b", Some(("docs/*", "https://docs.example.com/100%25/a%20b")))]
+    #[case("docs/café", Some(("docs/*", "https://docs.example.com/caf%C3%A9")))]
+    fn test_lookup(#[case] path: &str, #[case] expected: Option<(&str, &str)>) {
+        let (redirects, _) = parse_redirects(
+            &[
+                "foo http://foo.bar",
+                "docs/* https://docs.example.com/*",
+                "docs/api/* https://api.example.com/*",
+                "docs/exact http://exact.example",
+                "old/* https://example.com/moved",
+            ]
+            .join("\n"),
+        );
+
+        let result = redirects.lookup(path, &HeaderMap::new());
+        assert_eq!(result.as_ref().map(|m| (m.alias, m.url.as_str())), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "blog/2019/hello-world",
+        Some((r"^/blog/(\d{4})/(.*)$", "https://new.example.com/posts/hello-world?year=2019"))
+    )]
+    #[case(
+        "blog/hello-world",
+        Some((r"^/blog/(.*)$", "https://new.example.com/posts/hello-world"))
+    )]
+    #[case("news/latest", Some(("news/*", "https://news.example.com/latest")))]
+    #[case("exact", Some(("exact", "http://exact.example")))]
+    #[case("other/2019/hello-world", None)]
+    #[case(
+        "blog/2019/a?b#c",
+        Some((r"^/blog/(\d{4})/(.*)$", "https://new.example.com/posts/a%3Fb%23c?year=2019"))
+    )]
+    #[case(
+        "blog/2019/a\rb&admin=1",
+        Some((r"^/blog/(\d{4})/(.*)$", "https://new.example.com/posts/a%0Db%26admin=1?year=2019"))
+    )]
+    fn test_lookup_regex(#[case] path: &str, #[case] expected: Option<(&str, &str)>) {
+        let (redirects, _) = parse_redirects(
+            &[
+                r"^/blog/(\d{4})/(.*)$ -> https://new.example.com/posts/$2?year=$1",
+                r"^/blog/(.*)$ https://new.example.com/posts/$1",
+                r"^/exa(ct)$ https://never.example.com",
+                "news/* https://news.example.com/*",
+                "exact http://exact.example",
+                r"^/(unclosed https://bad.example.com",
+            ]
+            .join("\n"),
+        );
+
+        assert_eq!(redirects.len(), 5);
+
+        let result = redirects.lookup(path, &HeaderMap::new());
+        assert_eq!(result.as_ref().map(|m| (m.alias, m.url.as_str())), expected);
+    }
+
+    #[rstest]
+    #[case("foo http://foo.bar", Some(None))]
+    #[case("foo http://foo.bar query=pass", Some(Some(QueryPolicy::Pass)))]
+    #[case(
+        "foo http://foo.bar status=302 query=Merge",
+        Some(Some(QueryPolicy::Merge))
+    )]
+    #[case("foo http://foo.bar query=keep", None)]
+    fn test_parse_redirects_query(
+        #[case] input: &str,
+        #[case] expected: Option<Option<QueryPolicy>>,
+    ) {
+        let (redirects, _) = parse_redirects(input);
+        assert_eq!(redirects.get("foo").map(|r| r.query), expected);
+    }
+
+    #[rstest]
+    #[case(
+        QueryPolicy::Drop,
+        "http://foo.bar/?a=1",
+        Some("b=2"),
+        "http://foo.bar/?a=1"
+    )]
+    #[case(
+        QueryPolicy::Pass,
+        "http://foo.bar/",
+        Some("b=2"),
+        "http://foo.bar/?b=2"
+    )]
+    #[case(
+        QueryPolicy::Pass,
+        "http://foo.bar/?a=1",
+        Some("b=2"),
+        "http://foo.bar/?b=2"
+    )]
+    #[case(QueryPolicy::Pass, "http://foo.bar/?a=1", None, "http://foo.bar/?a=1")]
+    #[case(
+        QueryPolicy::Pass,
+        "http://foo.bar/?a=1",
+        Some(""),
+        "http://foo.bar/?a=1"
+    )]
+    #[case(
+        QueryPolicy::Merge,
+        "http://foo.bar/?a=1&b=1",
+        Some("b=2&c=3"),
+        "http://foo.bar/?a=1&b=2&c=3"
+    )]
+    #[case(
+        QueryPolicy::Merge,
+        "http://foo.bar/path#frag",
+        Some("ref=twitter"),
+        "http://foo.bar/path?ref=twitter#frag"
+    )]
+    #[case(QueryPolicy::Merge, "/github?a=1", Some("b=2"), "/github?a=1&b=2")]
+    #[case(
+        QueryPolicy::Pass,
+        "/github",
+        Some("ref=twitter"),
+        "/github?ref=twitter"
+    )]
+    fn test_query_policy_apply(
+        #[case] policy: QueryPolicy,
+        #[case] url: &str,
+        #[case] query: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(policy.apply(url, query), expected);
+    }
+
+    #[rstest]
+    #[case(None, "foo", Some("http://any.example/foo"))]
+    #[case(Some("go.example.com"), "foo", Some("http://go.example.com/foo"))]
+    #[case(Some("GO.example.com:8080"), "foo", Some("http://go.example.com/foo"))]
+    #[case(Some("example.org"), "foo", Some("http://example.org/foo"))]
+    #[case(Some("other.example"), "foo", Some("http://any.example/foo"))]
+    #[case(Some("go.example.com"), "bar", Some("http://any.example/bar"))]
+    #[case(Some("example.org"), "docs/x", Some("http://example.org/docs/x"))]
+    #[case(None, "docs/x", None)]
+    #[case(Some("[::1]:8080"), "foo", Some("http://ipv6.example/foo"))]
+    fn test_lookup_host(
+        #[case] host: Option<&str>,
+        #[case] path: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        let (redirects, _) = parse_redirects(
+            &[
+                "foo http://any.example/foo",
+                "bar http://any.example/bar",
+                "go.example.com foo http://go.example.com/foo",
+                "example.org foo http://example.org/foo status=302",
+                "example.org docs/* http://example.org/docs/*",
+                "[::1] foo http://ipv6.example/foo",
+            ]
+            .join("\n"),
+        );
+
+        assert_eq!(redirects.len(), 6);
+
+        let mut headers = HeaderMap::new();
+        if let Some(host) = host {
+            headers.insert(header::HOST, host.parse().unwrap());
+        }
+
+        let result = redirects.lookup(path, &headers);
+        assert_eq!(result.map(|m| m.url), expected.map(String::from));
+    }
+
+    #[test]
+    fn test_parse_document_host() {
+        let input = r#"{"redirects": [
+            {"alias": "foo", "url": "http://any.example"},
+            {"host": "go.example.com", "alias": "foo", "url": "http://go.example.com"}
+        ]}"#;
+        let (redirects, _) = parse_document(input, Format::Json).unwrap();
+
+        assert_eq!(redirects.get("foo").unwrap().url, "http://any.example");
+        assert_eq!(
+            redirects.get_for_host("go.example.com", "foo").unwrap().url,
+            "http://go.example.com"
+        );
+    }
+
+    #[rstest]
+    #[case("iPhone", "en", "https://apps.apple.com/app")]
+    #[case("Android", "de", "https://play.google.com/app")]
+    #[case("Linux", "de-DE", "https://example.com/de/app")]
+    #[case("Linux", "en", "https://example.com/app")]
+    fn test_lookup_conditional(
+        #[case] user_agent: &str,
+        #[case] language: &str,
+        #[case] expected: &str,
+    ) {
+        let input = r#"
+redirects:
+  - alias: app
+    url: https://example.com/app
+    targets:
+      - platform: ios
+        url: https://apps.apple.com/app
+      - platform: android
+        url: https://play.google.com/app
+      - language: de
+        url: https://example.com/de/app
+  - alias: bad
+    url: https://example.com/bad
+    targets:
+      - platform: ios
+        url: not-a-url
+"#;
+        let (redirects, _) = parse_document(input, Format::Yaml).unwrap();
+        assert_eq!(redirects.len(), 1);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, user_agent.parse().unwrap());
+        headers.insert(header::ACCEPT_LANGUAGE, language.parse().unwrap());
+
+        let result = redirects.lookup("app", &headers).unwrap();
+        assert_eq!(result.url, expected);
+    }
+
+    #[rstest]
+    #[case(
+        "servy_variant_signup=a",
+        "iPhone",
+        "https://apps.apple.com/signup",
+        None
+    )]
+    #[case("servy_variant_signup=a", "Linux", "https://example.com/a", Some("a"))]
+    #[case("servy_variant_signup=b", "Linux", "https://example.com/b", Some("b"))]
+    fn test_lookup_variants(
+        #[case] cookie: &str,
+        #[case] user_agent: &str,
+        #[case] expected_url: &str,
+        #[case] expected_variant: Option<&str>,
+    ) {
+        let input = r#"
+[[redirects]]
+alias = "signup"
+url = "https://example.com/signup"
+targets = [{ platform = "ios", url = "https://apps.apple.com/signup" }]
+variants = [
+  { label = "a", url = "https://example.com/a", weight = 90 },
+  { label = "b", url = "https://example.com/b", weight = 10 },
+]
+"#;
+        let (redirects, _) = parse_document(input, Format::Toml).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+        headers.insert(header::USER_AGENT, user_agent.parse().unwrap());
+
+        let result = redirects.lookup("signup", &headers).unwrap();
+        assert_eq!(result.url, expected_url);
+        assert_eq!(result.variant, expected_variant);
+    }
+
+    #[rstest]
+    #[case(
+        Format::Yaml,
+        r#"
+redirects:
+  - alias: handbook
+    url: https://example.com/handbook
+    description: The team handbook
+    tags: [docs, onboarding]
+    owner: platform
+    created: 2024-01-02T03:04:05Z
+"#
+    )]
+    #[case(
+        Format::Toml,
+        r#"
+[[redirects]]
+alias = "handbook"
+url = "https://example.com/handbook"
+description = "The team handbook"
+tags = ["docs", "onboarding"]
+owner = "platform"
+created = 2024-01-02T03:04:05Z
+"#
+    )]
+    fn test_parse_document_metadata(#[case] format: Format, #[case] input: &str) {
+        let (redirects, report) = parse_document(input, format).unwrap();
+        assert!(report.is_empty());
+
+        let metadata = &redirects.get("handbook").unwrap().metadata;
+        assert_eq!(metadata.description.as_deref(), Some("The team handbook"));
+        assert_eq!(metadata.tags, vec!["docs", "onboarding"]);
+        assert_eq!(metadata.owner.as_deref(), Some("platform"));
+        assert_eq!(
+            metadata.created.map(|t| t.unix_timestamp()),
+            Some(1704164645)
+        );
+        assert_eq!(metadata.updated, None);
+    }
+
+    #[test]
+    fn test_parse_document_invalid_timestamp() {
+        let input = json!({
+            "redirects": [
+                { "alias": "foo", "url": "http://foo.bar", "created": "yesterday" },
+                { "alias": "bar", "url": "http://bar.baz", "updated": 1704164645 },
+                { "alias": "baz", "url": "http://baz.qux", "created": "2024-01-02T03:04:05Z" },
+            ]
+        });
+        let (redirects, report) = parse_document(&input.to_string(), Format::Json).unwrap();
+
+        let diagnostics: Vec<(usize, &str, DiagnosticKind)> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.text.as_str(), d.kind))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (1, "foo", DiagnosticKind::InvalidOption),
+                (2, "bar", DiagnosticKind::InvalidOption),
+            ]
+        );
+        assert_eq!(redirects.len(), 1);
+        assert!(redirects.get("baz").unwrap().metadata.created.is_some());
+    }
+
+    #[rstest]
+    #[case(
+        r"^/(?<a>\w+)/(\w+)$",
+        "https://x.example/$2/${a}/$$/$1x/${3}",
+        "https://x.example/b/a/$//"
+    )]
+    #[case(
+        r"^/(\w+)/(\w+)$",
+        "https://x.example/$2?q=$1&$",
+        "https://x.example/b?q=a&$"
+    )]
+    #[case(r"^/(.+)$", "https://x.example/${1}", "https://x.example/a/b")]
+    fn test_expand_encoded(#[case] pattern: &str, #[case] target: &str, #[case] expected: &str) {
+        let regex = Regex::new(pattern).unwrap();
+        let captures = regex.captures("/a/b").unwrap();
+
+        let mut unencoded = String::new();
+        captures.expand(target, &mut unencoded);
+        assert_eq!(expand_encoded(&captures, target), expected);
+        // Text without any characters to encode expands as the regex crate does.
+        assert_eq!(unencoded, expected);
+    }
+
+    #[rstest]
+    #[case("gh", 3, Some(("github", "https://github.com/jnsgruk")))]
+    #[case("g", 3, Some(("github", "https://github.com/jnsgruk")))]
+    #[case("g", 1, None)]
+    #[case("code/servy", 3, Some(("github/*", "https://github.com/jnsgruk/servy")))]
+    // Forwarded paths are encoded once, rather than again at each hop.
+    #[case(
+        "code/a b?c",
+        3,
+        Some(("github/*", "https://github.com/jnsgruk/a%20b%3Fc"))
+    )]
+    #[case("about", 3, Some(("about", "/about-me")))]
+    #[case("nope", 3, None)]
+    fn test_resolve(
+        #[case] path: &str,
+        #[case] max_depth: usize,
+        #[case] expected: Option<(&str, &str)>,
+    ) {
+        let (redirects, _) = parse_redirects(
+            &[
+                "github https://github.com/jnsgruk",
+                "gh /github",
+                "g /gh/",
+                "code/* /github/*",
+                "github/* https://github.com/jnsgruk/*",
+                "about /about-me",
+            ]
+            .join("\n"),
+        );
+
+        let result = redirects.resolve(path, &HeaderMap::new(), max_depth);
+        assert_eq!(
+            result.map(|m| (m.alias, m.url)),
+            expected.map(|(a, u)| (a, u.to_string()))
+        );
+    }
+
+    #[rstest]
+    #[case(vec!["a /b", "b /a", "c /a", "d https://example.com"], 3, vec!["d"])]
+    #[case(vec!["a /a", "b https://example.com"], 3, vec!["b"])]
+    #[case(vec!["a /b", "b /c", "c /d", "d https://example.com"], 2, vec!["b", "c", "d"])]
+    #[case(vec!["a /b", "b /c", "c /d", "d https://example.com"], 3, vec!["a", "b", "c", "d"])]
+    #[case(vec!["a /missing", "b /c?x=1", "c https://example.com"], 1, vec!["a", "b", "c"])]
+    fn test_reject_invalid_chains(
+        #[case] input: Vec<&str>,
+        #[case] max_depth: usize,
+        #[case] expected: Vec<&str>,
+    ) {
+        let (mut redirects, mut report) = parse_redirects(&input.join("\n"));
+        let removed = redirects.reject_invalid_chains(max_depth, &mut report);
+
+        let mut aliases: Vec<&str> = redirects.iter().map(|(_, a, _)| a).collect();
+        aliases.sort();
+        assert_eq!(aliases, expected);
+        assert_eq!(removed, input.len() - expected.len());
+        assert_eq!(report.count(DiagnosticKind::AliasChain), removed);
+    }
+
+    #[test]
+    fn test_reject_invalid_chains_host() {
+        let (mut redirects, mut report) = parse_redirects(
+            &[
+                "a /b",
+                "b https://example.com",
+                "example.org b /a",
+                "example.org c /b",
+            ]
+            .join("\n"),
+        );
+
+        assert_eq!(redirects.reject_invalid_chains(3, &mut report), 2);
+        assert!(redirects.get("a").is_some());
+        assert!(redirects.get_for_host("example.org", "b").is_none());
+        assert!(redirects.get_for_host("example.org", "c").is_none());
+
+        let mut diagnostics: Vec<String> =
+            report.diagnostics.iter().map(|d| d.to_string()).collect();
+        diagnostics.sort();
+        assert_eq!(
+            diagnostics,
+            vec![
+                "line 3: alias cycle detected: b -> a -> b: 'example.org b /a'",
+                "line 4: alias cycle detected: c -> b -> a -> b: 'example.org c /b'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let (mut redirects, _) = parse_redirects(
+            &[
+                "foo https://primary.com/foo",
+                "docs/* https://primary.com/docs/*",
+                "^/blog/(.*)$ https://primary.com/blog/$1",
+                "example.org foo https://primary.org/foo",
+            ]
+            .join("\n"),
+        );
+        let (other, _) = parse_redirects(
+            &[
+                "foo https://secondary.com/foo",
+                "bar https://secondary.com/bar",
+                "docs/api/* https://secondary.com/api/*",
+                "^/blog/(.*)$ https://secondary.com/blog/$1",
+                "^/(.*)$ https://secondary.com/$1",
+                "example.org foo https://secondary.org/foo",
+                "example.net foo https://secondary.net/foo",
+            ]
+            .join("\n"),
+        );
+
+        let conflicts = redirects.merge(other);
+        assert_eq!(
+            conflicts,
+            vec![
+                (None, "^/blog/(.*)$".to_string()),
+                (None, "foo".to_string()),
+                (Some("example.org".to_string()), "foo".to_string()),
+            ]
+        );
+        assert_eq!(redirects.len(), 8);
+
+        let headers = HeaderMap::new();
+        let url = |path| redirects.lookup(path, &headers).map(|m| m.url);
+        assert_eq!(url("foo").unwrap(), "https://primary.com/foo");
+        assert_eq!(url("bar").unwrap(), "https://secondary.com/bar");
+        assert_eq!(url("docs/api/v1").unwrap(), "https://secondary.com/api/v1");
+        assert_eq!(url("docs/guide").unwrap(), "https://primary.com/docs/guide");
+        assert_eq!(url("blog/post").unwrap(), "https://primary.com/blog/post");
+        assert_eq!(url("other").unwrap(), "https://secondary.com/other");
+        assert_eq!(
+            redirects.get_for_host("example.org", "foo").unwrap().url,
+            "https://primary.org/foo"
+        );
+        assert_eq!(
+            redirects.get_for_host("example.net", "foo").unwrap().url,
+            "https://secondary.net/foo"
+        );
+    }
+
+    #[test]
+    fn test_parse_redirects_report() {
+        let input = [
+            "foo http://foo.bar",
+            "",
+            "garbagethatshouldntbeparsed",
+            "good-key but-a-bad-url",
+            "bar http://bar.baz status=200",
+            "^/(unclosed http://example.com",
+            "foo http://foo.baz",
+            "nested http://example.com/nested",
+            "example.org foo http://example.org",
+        ];
+        let (redirects, report) = parse_redirects(&input.join("\n"));
+
+        let diagnostics: Vec<(usize, &str, DiagnosticKind)> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.text.as_str(), d.kind))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (3, input[2], DiagnosticKind::FieldCount),
+                (4, input[3], DiagnosticKind::InvalidUrl),
+                (5, input[4], DiagnosticKind::InvalidOption),
+                (6, input[5], DiagnosticKind::InvalidRegex),
+                (7, input[6], DiagnosticKind::DuplicateAlias),
+                (8, input[7], DiagnosticKind::AssetConflict),
+            ]
+        );
+        assert_eq!(redirects.get("foo").unwrap().url, "http://foo.baz");
+        assert!(redirects.get("nested").is_some());
+    }
+
+    #[test]
+    fn test_parse_document_report() {
+        let input = r#"
+redirects:
+  - alias: foo
+    url: http://foo.bar
+  - alias: bar
+    url: http://bar.baz
+    status: 200
+  - alias: baz
+    url: not-a-url
+  - alias: qux
+    url: http://qux.quux
+    variants:
+      - { label: "a; Domain=evil", url: "http://qux.quux/a", weight: 1 }
+  - alias: quux
+    url: http://quux.corge
+    variants:
+      - { label: a, url: "http://quux.corge/a", weight: 1 }
+      - { label: a, url: "http://quux.corge/b", weight: 1 }
+"#;
+        let (redirects, report) = parse_document(input, Format::Yaml).unwrap();
+
+        let diagnostics: Vec<(usize, &str, DiagnosticKind)> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.text.as_str(), d.kind))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (2, "bar", DiagnosticKind::InvalidOption),
+                (3, "baz", DiagnosticKind::InvalidUrl),
+                (4, "qux", DiagnosticKind::InvalidOption),
+                (5, "quux", DiagnosticKind::InvalidOption),
+            ]
+        );
+        assert_eq!(redirects.len(), 1);
+    }
+
+    #[rstest]
+    #[case("status=302", true)]
+    #[case("query=merge", true)]
+    #[case("http://example.com/?a=1", false)]
+    #[case("example.com", false)]
+    #[case("=foo", false)]
+    fn test_is_line_option(#[case] token: &str, #[case] expected: bool) {
+        assert_eq!(is_line_option(token), expected);
+    }
+
+    #[rstest]
+    #[case(301, true)]
+    #[case(302, true)]
+    #[case(303, true)]
+    #[case(307, true)]
+    #[case(308, true)]
+    #[case(200, false)]
+    #[case(304, false)]
+    #[case(404, false)]
+    fn test_redirect_status(#[case] code: u16, #[case] valid: bool) {
+        assert_eq!(redirect_status(code).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case(Format::Lines, "foo http://foo.bar\nbar http://bar.baz\nbad not-a-url")]
+    #[case(
+        Format::Json,
+        r#"{"redirects": [
+            {"alias": "foo", "url": "http://foo.bar"},
+            {"alias": "bar", "url": "http://bar.baz", "extra": "ignored"},
+            {"alias": "bad", "url": "not-a-url"}
+        ]}"#
+    )]
+    #[case(
+        Format::Yaml,
+        "redirects:\n  - alias: foo\n    url: http://foo.bar\n  - alias: bar\n    url: http://bar.baz\n  - alias: bad\n    url: not-a-url\n"
+    )]
+    #[case(
+        Format::Toml,
+        "[[redirects]]\nalias = \"foo\"\nurl = \"http://foo.bar\"\n\n[[redirects]]\nalias = \"bar\"\nurl = \"http://bar.baz\"\n\n[[redirects]]\nalias = \"bad\"\nurl = \"not-a-url\"\n"
+    )]
+    fn test_parse_document(#[case] format: Format, #[case] input: &str) {
+        let (redirects, _) = parse_document(input, format).unwrap();
+
+        assert_eq!(redirects.len(), 2);
+        assert_eq!(redirects.get("foo").unwrap().url, "http://foo.bar");
+        assert_eq!(redirects.get("bar").unwrap().url, "http://bar.baz");
+    }
+
+    #[rstest]
+    #[case(Format::Json, r#"{"redirects": [{"alias": "foo", "url": "http://foo.bar", "status": 302, "query": "merge"}, {"alias": "bad", "url": "http://foo.bar", "status": 200}]}"#)]
+    #[case(Format::Yaml, "redirects:\n  - alias: foo\n    url: http://foo.bar\n    status: 302\n    query: merge\n  - alias: bad\n    url: http://foo.bar\n    status: 200\n")]
+    #[case(Format::Toml, "[[redirects]]\nalias = \"foo\"\nurl = \"http://foo.bar\"\nstatus = 302\nquery = \"merge\"\n\n[[redirects]]\nalias = \"bad\"\nurl = \"http://foo.bar\"\nstatus = 200\n")]
+    fn test_parse_document_options(#[case] format: Format, #[case] input: &str) {
+        let (redirects, _) = parse_document(input, format).unwrap();
+
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(
+            redirects.get("foo").unwrap().status,
+            Some(StatusCode::FOUND)
+        );
+    }
+
+    #[rstest]
+    #[case(Format::Json, "{not json")]
+    #[case(Format::Yaml, "redirects: [")]
+    #[case(Format::Toml, "[[redirects]\n")]
+    fn test_parse_document_invalid(#[case] format: Format, #[case] input: &str) {
+        assert!(parse_document(input, format).is_err());
+    }
+
+    #[rstest]
+    #[case("application/json", Some(Format::Json))]
+    #[case("application/json; charset=utf-8", Some(Format::Json))]
+    #[case("application/x-yaml", Some(Format::Yaml))]
+    #[case("text/yaml", Some(Format::Yaml))]
+    #[case("application/toml", Some(Format::Toml))]
+    #[case("text/plain; charset=utf-8", None)]
+    fn test_format_from_content_type(#[case] content_type: &str, #[case] expected: Option<Format>) {
+        assert_eq!(Format::from_content_type(content_type), expected);
+    }
+
+    #[rstest]
+    #[case("/srv/redirects.json", Some(Format::Json))]
+    #[case("redirects.YAML", Some(Format::Yaml))]
+    #[case("/gist/raw/redirects.yml", Some(Format::Yaml))]
+    #[case("redirects.toml", Some(Format::Toml))]
+    #[case("/gist/raw", None)]
+    #[case("redirects.txt", None)]
+    fn test_format_from_path(#[case] path: &str, #[case] expected: Option<Format>) {
+        assert_eq!(Format::from_path(path), expected);
+    }
 }
@@ -15,7 +15,12 @@ use tower::ServiceBuilder;
 use tracing::{info, info_span, Span};
 
 use crate::{
-    handlers::{default_handler, error_handler, root_handler},
+    api::{api_router, API_PREFIX},
+    handlers::{default_handler, error_handler, qr_handler, root_handler},
+    links::{
+        links_handler, links_json_handler, opensearch_handler, LINKS_JSON_PATH, LINKS_PATH,
+        OPENSEARCH_PATH,
+    },
     metrics::{init_metrics, metrics_middleware},
     AppContext, Config,
 };
@@ -35,7 +40,24 @@ async fn start_app_server(config: &Config) -> Result<()> {
         .with_context(|| format!("failed to start app server listener on: {}", socket))?;
 
     let context = AppContext::new(config.clone());
-    context.refresh_redirects().await?;
+
+    // If a source is unavailable, start with the cached redirects if there are any, and keep
+    // trying to reach it in the background. Only refuse to start if there is nothing to serve.
+    if let Err(e) = context.refresh_redirects().await {
+        tracing::warn!("failed to refresh redirects: {e:#}");
+        if config.cache_path.is_some() {
+            if let Err(e) = context.load_cached_redirects().await {
+                tracing::warn!("failed to load redirects from cache: {e:#}");
+            }
+        }
+        if !context.has_documents() {
+            return Err(e);
+        }
+        context.retry_refresh();
+    }
+    if !context.watch_redirects()? {
+        context.refresh_periodically();
+    }
 
     let app = servy_router(context)?;
 
@@ -80,11 +102,22 @@ pub fn metrics_router() -> Result<Router> {
 }
 
 /// Construct and return an Axum router for the main Servy app which includes configuration
-/// for tracing, load shedding and compression.
+/// for tracing, load shedding and compression. The admin API is included if an API token and a
+/// store are configured.
 pub fn servy_router(context: AppContext) -> Result<Router> {
-    let router = Router::new()
+    let mut router = Router::new()
         .route("/*key", get(default_handler))
-        .route("/", get(root_handler))
+        .route("/_qr/*alias", get(qr_handler))
+        .route(LINKS_PATH, get(links_handler))
+        .route(LINKS_JSON_PATH, get(links_json_handler))
+        .route(OPENSEARCH_PATH, get(opensearch_handler))
+        .route("/", get(root_handler));
+
+    if context.config().api_token.is_some() && context.store().is_some() {
+        router = router.nest(API_PREFIX, api_router(context.clone()));
+    }
+
+    let router = router
         .route_layer(middleware::from_fn(metrics_middleware))
         .layer(
             ServiceBuilder::new()
@@ -0,0 +1,156 @@
+use anyhow::Result;
+use rand::Rng;
+
+use crate::{handlers::is_asset, redirects::RedirectEntry, store::RedirectStore, AppContext};
+
+/// The characters short keys are made of: the base62 alphabet without the characters which are
+/// easily confused with one another (`0`, `O`, `o`, `1`, `I` and `l`).
+const KEY_ALPHABET: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnpqrstuvwxyz";
+
+/// The number of keys tried before giving up on minting a unique one.
+const MAX_ATTEMPTS: usize = 16;
+
+/// Generate a random short key of a given length.
+pub fn generate_key(length: usize) -> String {
+    let mut rng = rand::rng();
+    (0..length)
+        .map(|_| KEY_ALPHABET[rng.random_range(0..KEY_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Report whether a key is already taken, either by a redirect from any source or by an asset.
+fn is_taken(key: &str, context: &AppContext) -> bool {
+    is_asset(key) || context.redirects().iter().any(|(_, alias, _)| alias == key)
+}
+
+/// Mint a new short key for an entry and add it to the store, returning the entry with its new
+/// alias. Returns `None` if no unused key could be found, which means the configured key length
+/// is too short for the number of links.
+pub(crate) async fn mint_link(
+    context: &AppContext,
+    store: &RedirectStore,
+    entry: RedirectEntry,
+) -> Result<Option<RedirectEntry>> {
+    let length = context.config().short_key_length;
+    mint_link_with(store, length, entry, |key| is_taken(key, context)).await
+}
+
+/// Mint a new short key of a given length for an entry and add it to the store, skipping the
+/// keys which `is_taken` reports are already in use.
+async fn mint_link_with(
+    store: &RedirectStore,
+    length: usize,
+    mut entry: RedirectEntry,
+    mut is_taken: impl FnMut(&str) -> bool,
+) -> Result<Option<RedirectEntry>> {
+    for _ in 0..MAX_ATTEMPTS {
+        entry.alias = generate_key(length);
+        if is_taken(&entry.alias) {
+            continue;
+        }
+        if store.create(entry.clone()).await? {
+            return Ok(Some(entry));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(1)]
+    #[case(6)]
+    #[case(32)]
+    fn test_generate_key(#[case] length: usize) {
+        let key = generate_key(length);
+        assert_eq!(key.len(), length);
+        assert!(key.bytes().all(|c| KEY_ALPHABET.contains(&c)));
+    }
+
+    #[test]
+    fn test_key_alphabet() {
+        assert_eq!(KEY_ALPHABET.len(), 56);
+        for c in b"0Oo1Il" {
+            assert!(!KEY_ALPHABET.contains(c));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mint_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default_with_redirects("");
+        config.store_path = Some(dir.path().join("store.json"));
+        config.short_key_length = 1;
+        let context = AppContext::new(config);
+        let store = context.store().unwrap();
+
+        // Mint every key of length one, then check that no more can be minted.
+        let mut keys = Vec::new();
+        while let Some(entry) =
+            mint_link(&context, store, RedirectEntry::new("", "https://foo.bar"))
+                .await
+                .unwrap()
+        {
+            keys.push(entry.alias);
+            context.refresh_redirects().await.unwrap();
+        }
+
+        keys.sort();
+        keys.dedup();
+        assert!(!keys.is_empty());
+        assert!(keys
+            .iter()
+            .all(|key| key.len() == 1 && KEY_ALPHABET.contains(&key.as_bytes()[0])));
+        assert_eq!(store.list().await.unwrap().len(), keys.len());
+    }
+
+    #[tokio::test]
+    async fn test_mint_link_skips_taken_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RedirectStore::new(dir.path().join("store.json"));
+
+        // The first candidates are taken, so the key minted is the one after them.
+        let mut candidates = Vec::new();
+        let entry = mint_link_with(
+            &store,
+            8,
+            RedirectEntry::new("", "https://foo.bar"),
+            |key| {
+                candidates.push(key.to_string());
+                candidates.len() <= 3
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(candidates.len(), 4);
+        assert_eq!(entry.alias, candidates[3]);
+        assert!(entry.alias.bytes().all(|c| KEY_ALPHABET.contains(&c)));
+        let stored = store.list().await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].alias, entry.alias);
+    }
+
+    #[tokio::test]
+    async fn test_mint_link_exhausted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RedirectStore::new(dir.path().join("store.json"));
+
+        let mut attempts = 0;
+        let entry = mint_link_with(&store, 8, RedirectEntry::new("", "https://foo.bar"), |_| {
+            attempts += 1;
+            true
+        })
+        .await
+        .unwrap();
+
+        assert!(entry.is_none());
+        assert_eq!(attempts, MAX_ATTEMPTS);
+        assert!(store.list().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use minisign_verify::{PublicKey, Signature};
+
+/// The suffix added to the location of a redirects list to find its detached signature.
+pub const SIGNATURE_SUFFIX: &str = ".minisig";
+
+/// Parse a minisign public key, given as the base64 encoded key on the second line of a
+/// minisign `.pub` file.
+pub fn parse_public_key(public_key: &str) -> Result<PublicKey> {
+    PublicKey::from_base64(public_key.trim()).context("invalid minisign public key")
+}
+
+/// Verify that a detached minisign signature of the contents of a redirects list was made by the
+/// secret key matching the given public key. Only signatures of the pre-hashed kind, which
+/// minisign has created by default since version 0.8, are accepted.
+pub fn verify_signature(public_key: &str, contents: &str, signature: &str) -> Result<()> {
+    let public_key = parse_public_key(public_key)?;
+    let signature = Signature::decode(signature).context("invalid minisign signature")?;
+
+    public_key
+        .verify(contents.as_bytes(), &signature, false)
+        .context("redirects list does not match its signature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const PUBLIC_KEY: &str = "RWTzMLesUreJW7gvFeTMLAHFxcQO1/yimECvWuWhpkh9tXGtgK2XRE/o";
+    const CONTENTS: &str = include_str!("../../tests/fixtures/signed_redirects");
+    const SIGNATURE: &str = include_str!("../../tests/fixtures/signed_redirects.minisig");
+
+    #[rstest]
+    #[case(PUBLIC_KEY)]
+    #[case("  RWTzMLesUreJW7gvFeTMLAHFxcQO1/yimECvWuWhpkh9tXGtgK2XRE/o\n")]
+    fn test_verify_signature(#[case] public_key: &str) {
+        verify_signature(public_key, CONTENTS, SIGNATURE).unwrap();
+    }
+
+    #[rstest]
+    #[case::tampered_contents(PUBLIC_KEY, "foo http://evil.example\n", SIGNATURE)]
+    #[case::other_key(
+        "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3",
+        CONTENTS,
+        SIGNATURE
+    )]
+    #[case::invalid_key("not-a-key", CONTENTS, SIGNATURE)]
+    #[case::invalid_signature(PUBLIC_KEY, CONTENTS, "not a signature")]
+    fn test_verify_signature_invalid(
+        #[case] public_key: &str,
+        #[case] contents: &str,
+        #[case] signature: &str,
+    ) {
+        assert!(verify_signature(public_key, contents, signature).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use reqwest::{
+    header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{redirects::Format, signatures::SIGNATURE_SUFFIX};
+
+/// The raw contents of a redirects list, along with the format it is written in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Document {
+    pub contents: String,
+    pub format: Format,
+    /// Identifies the version of the document, so that it is only fetched again if it changes.
+    pub validators: Validators,
+}
+
+impl Document {
+    /// Construct a new Document from its contents and format.
+    pub fn new(contents: &str, format: Format) -> Self {
+        Self {
+            contents: contents.to_string(),
+            format,
+            validators: Validators::default(),
+        }
+    }
+}
+
+/// The cache validators returned by an upstream source, used to make conditional requests for
+/// a redirects list that only transfer it if it has changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    /// The value of the `ETag` header.
+    pub etag: Option<String>,
+    /// The value of the `Last-Modified` header.
+    pub last_modified: Option<String>,
+}
+
+/// A RedirectSource is a backend from which the raw contents of a redirects list can be fetched.
+/// Servy ships with HTTP(S), local file and inline implementations, but library users can
+/// implement this trait to load redirects from elsewhere.
+#[async_trait]
+pub trait RedirectSource: Debug + Send + Sync {
+    /// Fetch the raw contents of the redirects list from the source.
+    async fn fetch(&self) -> Result<Document>;
+
+    /// Fetch the raw contents of the redirects list, unless it is unchanged since the fetch
+    /// which returned the given validators, in which case `None` is returned. Sources which
+    /// cannot tell whether the list has changed always fetch it.
+    async fn fetch_if_changed(&self, _validators: &Validators) -> Result<Option<Document>> {
+        self.fetch().await.map(Some)
+    }
+
+    /// Fetch the detached minisign signature of the redirects list, which is published next to
+    /// the list with a `.minisig` suffix. Sources which cannot hold a signature return an error.
+    async fn fetch_signature(&self) -> Result<String> {
+        bail!("source does not support signatures: {}", self.location())
+    }
+
+    /// Return a human readable description of where the redirects are loaded from.
+    fn location(&self) -> &str;
+
+    /// Report whether the redirects list must be signed when a public key is configured. Only
+    /// sources managed by Servy itself, such as its writable store, are exempt.
+    fn requires_signature(&self) -> bool {
+        true
+    }
+
+    /// Return the path of a local file which can be watched for changes, if the source
+    /// is backed by one.
+    fn watch_path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Construct the appropriate RedirectSource for a given location. Locations with a `file://`
+/// prefix are read from local disk, and all other locations are fetched over HTTP(S).
+pub fn source_from_location(location: &str) -> Arc<dyn RedirectSource> {
+    match location.strip_prefix("file://") {
+        Some(path) => Arc::new(FileSource::new(path)),
+        None => Arc::new(HttpSource::new(location)),
+    }
+}
+
+/// A RedirectSource which fetches the redirects list from a URL over HTTP(S).
+#[derive(Clone, Debug)]
+pub struct HttpSource {
+    url: String,
+    client: Client,
+}
+
+impl HttpSource {
+    /// Construct a new HttpSource for a given URL.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl RedirectSource for HttpSource {
+    async fn fetch(&self) -> Result<Document> {
+        self.fetch_if_changed(&Validators::default())
+            .await?
+            .context("redirects list was not modified")
+    }
+
+    async fn fetch_if_changed(&self, validators: &Validators) -> Result<Option<Document>> {
+        tracing::info!("fetching redirects from url: {}", self.url);
+
+        // Make a conditional request if the list has been fetched before, so that it is only
+        // transferred again if it has changed.
+        let mut request = self.client.get(&self.url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let resp = request.send().await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            tracing::info!("redirects not modified since last fetch: {}", self.url);
+            return Ok(None);
+        }
+
+        // Treat error responses as a failure, rather than parsing an error page as redirects.
+        let resp = resp.error_for_status()?;
+
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        };
+
+        // Prefer the format advertised by the server, falling back to the extension of the
+        // URL's path, and finally to the default line-based format.
+        let format = header(CONTENT_TYPE)
+            .as_deref()
+            .and_then(Format::from_content_type)
+            .or_else(|| {
+                Url::parse(&self.url)
+                    .ok()
+                    .and_then(|url| Format::from_path(url.path()))
+            })
+            .unwrap_or_default();
+
+        let validators = Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+
+        Ok(Some(Document {
+            contents: resp.text().await?,
+            format,
+            validators,
+        }))
+    }
+
+    async fn fetch_signature(&self) -> Result<String> {
+        // Add the suffix to the URL's path, so that any query string is kept after it.
+        let url = match Url::parse(&self.url) {
+            Ok(mut url) => {
+                url.set_path(&format!("{}{SIGNATURE_SUFFIX}", url.path()));
+                url.to_string()
+            }
+            Err(_) => format!("{}{SIGNATURE_SUFFIX}", self.url),
+        };
+        tracing::info!("fetching redirects signature from url: {url}");
+
+        let resp = self.client.get(&url).send().await?.error_for_status()?;
+        Ok(resp.text().await?)
+    }
+
+    fn location(&self) -> &str {
+        &self.url
+    }
+}
+
+/// A RedirectSource which reads the redirects list from a file on local disk.
+#[derive(Clone, Debug)]
+pub struct FileSource {
+    path: PathBuf,
+    location: String,
+}
+
+impl FileSource {
+    /// Construct a new FileSource for a given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            location: format!("file://{}", path.display()),
+            path,
+        }
+    }
+}
+
+#[async_trait]
+impl RedirectSource for FileSource {
+    async fn fetch(&self) -> Result<Document> {
+        tracing::info!("reading redirects from file: {}", self.path.display());
+
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .with_context(|| format!("failed to read redirects file: {}", self.path.display()))?;
+
+        let format = self
+            .path
+            .to_str()
+            .and_then(Format::from_path)
+            .unwrap_or_default();
+
+        Ok(Document::new(&contents, format))
+    }
+
+    async fn fetch_signature(&self) -> Result<String> {
+        let mut path = self.path.as_os_str().to_os_string();
+        path.push(SIGNATURE_SUFFIX);
+        let path = PathBuf::from(path);
+
+        tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("failed to read redirects signature: {}", path.display()))
+    }
+
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn watch_path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+/// A RedirectSource which serves an inline redirects list held in memory.
+#[derive(Clone, Debug)]
+pub struct StaticSource {
+    document: Document,
+}
+
+impl StaticSource {
+    /// Construct a new StaticSource from the contents of a redirects list in the default
+    /// line-based format.
+    pub fn new(contents: &str) -> Self {
+        Self::with_format(contents, Format::Lines)
+    }
+
+    /// Construct a new StaticSource from the contents of a redirects list in a given format.
+    pub fn with_format(contents: &str, format: Format) -> Self {
+        Self {
+            document: Document::new(contents, format),
+        }
+    }
+}
+
+#[async_trait]
+impl RedirectSource for StaticSource {
+    async fn fetch(&self) -> Result<Document> {
+        Ok(self.document.clone())
+    }
+
+    fn location(&self) -> &str {
+        "inline"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+    use rstest::rstest;
+    use std::io::Write;
+
+    #[rstest]
+    #[case("http://example.com/redirects", "http://example.com/redirects")]
+    #[case("https://example.com/redirects", "https://example.com/redirects")]
+    #[case("file:///srv/redirects", "file:///srv/redirects")]
+    #[case("file://redirects", "file://redirects")]
+    fn test_source_from_location(#[case] location: &str, #[case] expected: &str) {
+        let source = source_from_location(location);
+        assert_eq!(source.location(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_http_source_fetch() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/mock_redirects")
+            .match_query(mockito::Matcher::Missing)
+            .with_body("foo http://foo.bar")
+            .create_async()
+            .await;
+
+        let source = HttpSource::new(&format!("{}/mock_redirects", server.url()));
+        assert_eq!(
+            source.fetch().await.unwrap(),
+            Document::new("foo http://foo.bar", Format::Lines)
+        );
+    }
+
+    #[rstest]
+    #[case(Some("\"v1\""), None)]
+    #[case(None, Some("Wed, 21 Oct 2015 07:28:00 GMT"))]
+    #[case(Some("\"v1\""), Some("Wed, 21 Oct 2015 07:28:00 GMT"))]
+    #[tokio::test]
+    async fn test_http_source_fetch_if_changed(
+        #[case] etag: Option<&str>,
+        #[case] last_modified: Option<&str>,
+    ) {
+        let mut server = Server::new_async().await;
+        let mut unconditional = server
+            .mock("GET", "/mock_redirects")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .match_header("if-modified-since", mockito::Matcher::Missing)
+            .with_body("foo http://foo.bar");
+        if let Some(etag) = etag {
+            unconditional = unconditional.with_header("etag", etag);
+        }
+        if let Some(last_modified) = last_modified {
+            unconditional = unconditional.with_header("last-modified", last_modified);
+        }
+        unconditional.create_async().await;
+
+        let mut conditional = server.mock("GET", "/mock_redirects").with_status(304);
+        if let Some(etag) = etag {
+            conditional = conditional.match_header("if-none-match", etag);
+        }
+        if let Some(last_modified) = last_modified {
+            conditional = conditional.match_header("if-modified-since", last_modified);
+        }
+        conditional.create_async().await;
+
+        let source = HttpSource::new(&format!("{}/mock_redirects", server.url()));
+
+        let document = source
+            .fetch_if_changed(&Validators::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(document.validators.etag.as_deref(), etag);
+        assert_eq!(document.validators.last_modified.as_deref(), last_modified);
+
+        let document = source.fetch_if_changed(&document.validators).await.unwrap();
+        assert!(document.is_none());
+    }
+
+    #[rstest]
+    #[case("/redirects", "application/json", Format::Json)]
+    #[case("/redirects", "application/x-yaml; charset=utf-8", Format::Yaml)]
+    #[case("/redirects.toml", "text/plain; charset=utf-8", Format::Toml)]
+    #[case("/redirects.yaml", "application/json", Format::Json)]
+    #[case("/redirects", "text/plain; charset=utf-8", Format::Lines)]
+    #[tokio::test]
+    async fn test_http_source_format(
+        #[case] path: &str,
+        #[case] content_type: &str,
+        #[case] expected: Format,
+    ) {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", path)
+            .match_query(mockito::Matcher::Any)
+            .with_header("content-type", content_type)
+            .create_async()
+            .await;
+
+        let source = HttpSource::new(&format!("{}{}", server.url(), path));
+        assert_eq!(source.fetch().await.unwrap().format, expected);
+    }
+
+    #[tokio::test]
+    async fn test_http_source_fetch_error_status() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/mock_redirects")
+            .with_status(503)
+            .with_body("foo http://foo.bar")
+            .create_async()
+            .await;
+
+        let source = HttpSource::new(&format!("{}/mock_redirects", server.url()));
+        assert!(source.fetch().await.is_err());
+    }
+
+    #[rstest]
+    #[case("/mock_redirects", "/mock_redirects.minisig")]
+    #[case("/mock_redirects?token=abc", "/mock_redirects.minisig?token=abc")]
+    #[tokio::test]
+    async fn test_http_source_fetch_signature(#[case] path: &str, #[case] expected: &str) {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", expected)
+            .with_body("untrusted comment: signature")
+            .create_async()
+            .await;
+
+        let source = HttpSource::new(&format!("{}{}", server.url(), path));
+        assert_eq!(
+            source.fetch_signature().await.unwrap(),
+            "untrusted comment: signature"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_source_fetch_signature_missing() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/mock_redirects.minisig")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let source = HttpSource::new(&format!("{}/mock_redirects", server.url()));
+        assert!(source.fetch_signature().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_source_fetch() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "foo http://foo.bar").unwrap();
+
+        let source = FileSource::new(file.path());
+        assert_eq!(
+            source.fetch().await.unwrap(),
+            Document::new("foo http://foo.bar", Format::Lines)
+        );
+        assert_eq!(source.watch_path(), Some(file.path()));
+    }
+
+    #[rstest]
+    #[case("redirects.json", Format::Json)]
+    #[case("redirects.yml", Format::Yaml)]
+    #[case("redirects.toml", Format::Toml)]
+    #[case("redirects", Format::Lines)]
+    #[tokio::test]
+    async fn test_file_source_format(#[case] file_name: &str, #[case] expected: Format) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(file_name);
+        std::fs::write(&path, "").unwrap();
+
+        let source = FileSource::new(path);
+        assert_eq!(source.fetch().await.unwrap().format, expected);
+    }
+
+    #[tokio::test]
+    async fn test_file_source_missing_file() {
+        let source = FileSource::new("/this/file/does/not/exist");
+        assert!(source.fetch().await.is_err());
+        assert!(source.fetch_signature().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_source_fetch_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects");
+        std::fs::write(dir.path().join("redirects.minisig"), "untrusted comment").unwrap();
+
+        let source = FileSource::new(path);
+        assert_eq!(source.fetch_signature().await.unwrap(), "untrusted comment");
+    }
+
+    #[tokio::test]
+    async fn test_static_source_fetch() {
+        let source = StaticSource::new("foo http://foo.bar");
+        assert_eq!(
+            source.fetch().await.unwrap(),
+            Document::new("foo http://foo.bar", Format::Lines)
+        );
+        assert_eq!(source.location(), "inline");
+        assert!(source.watch_path().is_none());
+        assert!(source.fetch_signature().await.is_err());
+    }
+}
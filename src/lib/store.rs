@@ -0,0 +1,262 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::{
+    cache::write_atomically,
+    metadata::now,
+    redirects::{parse_document, Format, RedirectEntry, RedirectsDocument},
+    sources::{Document, RedirectSource},
+};
+
+/// A writable store of redirects, kept in a JSON redirects list on local disk so that changes
+/// made at runtime survive restarts. The store is also a [`RedirectSource`], so that its
+/// redirects are merged with those from the read-only sources.
+#[derive(Debug)]
+pub struct RedirectStore {
+    path: PathBuf,
+    location: String,
+    /// Held while the store is modified, so that concurrent changes are not lost.
+    lock: Mutex<()>,
+}
+
+impl RedirectStore {
+    /// Construct a new RedirectStore backed by a given file, which is created on the first change
+    /// if it does not exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            location: format!("store://{}", path.display()),
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Return the path of the file backing the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return every entry in the store, in the order they were added.
+    pub async fn list(&self) -> Result<Vec<RedirectEntry>> {
+        Ok(self.load().await?.redirects)
+    }
+
+    /// Return the entry for a given alias, if there is one.
+    pub async fn get(&self, alias: &str) -> Result<Option<RedirectEntry>> {
+        let document = self.load().await?;
+        Ok(document.redirects.into_iter().find(|e| e.alias == alias))
+    }
+
+    /// Add a new entry to the store, recording when it was created unless the entry already says.
+    /// Returns `false` without changing the store if there is already an entry for the alias.
+    pub async fn create(&self, mut entry: RedirectEntry) -> Result<bool> {
+        let _guard = self.lock.lock().await;
+        let mut document = self.load().await?;
+        if document.redirects.iter().any(|e| e.alias == entry.alias) {
+            return Ok(false);
+        }
+
+        let metadata = &mut entry.metadata;
+        metadata.created = metadata.created.or_else(|| Some(now()));
+        metadata.updated = metadata.updated.or(metadata.created);
+        document.redirects.push(entry);
+        self.save(&document).await?;
+        Ok(true)
+    }
+
+    /// Replace the entry for an alias, keeping its creation time and recording when it was
+    /// updated. Returns `false` without changing the store if there is no entry for the alias.
+    pub async fn update(&self, mut entry: RedirectEntry) -> Result<bool> {
+        let _guard = self.lock.lock().await;
+        let mut document = self.load().await?;
+        let Some(existing) = document
+            .redirects
+            .iter_mut()
+            .find(|e| e.alias == entry.alias)
+        else {
+            return Ok(false);
+        };
+
+        let metadata = &mut entry.metadata;
+        metadata.created = metadata.created.or(existing.metadata.created);
+        metadata.updated = Some(now());
+        *existing = entry;
+        self.save(&document).await?;
+        Ok(true)
+    }
+
+    /// Remove the entry for an alias. Returns `false` if there is no entry for the alias.
+    pub async fn delete(&self, alias: &str) -> Result<bool> {
+        let _guard = self.lock.lock().await;
+        let mut document = self.load().await?;
+        let count = document.redirects.len();
+        document.redirects.retain(|e| e.alias != alias);
+        if document.redirects.len() == count {
+            return Ok(false);
+        }
+
+        self.save(&document).await?;
+        Ok(true)
+    }
+
+    /// Read the contents of the store, which is empty if its file does not exist yet.
+    async fn read(&self) -> Result<String> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Ok(serde_json::to_string(&RedirectsDocument::default())?)
+            }
+            Err(e) => Err(e).with_context(|| {
+                format!("failed to read redirects store: {}", self.path.display())
+            }),
+        }
+    }
+
+    /// Read and deserialise the contents of the store.
+    async fn load(&self) -> Result<RedirectsDocument> {
+        let contents = self.read().await?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("invalid redirects store: {}", self.path.display()))
+    }
+
+    /// Write the contents of the store to its file.
+    async fn save(&self, document: &RedirectsDocument) -> Result<()> {
+        let contents = serde_json::to_vec_pretty(document)?;
+        write_atomically(&self.path, &contents)
+            .await
+            .with_context(|| format!("failed to write redirects store: {}", self.path.display()))
+    }
+}
+
+#[async_trait]
+impl RedirectSource for RedirectStore {
+    async fn fetch(&self) -> Result<Document> {
+        let contents = self.read().await?;
+        Ok(Document::new(&contents, Format::Json))
+    }
+
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn requires_signature(&self) -> bool {
+        false
+    }
+}
+
+/// Check that an entry would be loaded as a valid redirect, returning an error describing the
+/// problems found if not.
+pub fn validate_entry(entry: &RedirectEntry) -> Result<()> {
+    if entry.alias.is_empty() {
+        bail!("alias must not be empty");
+    }
+
+    let document = RedirectsDocument {
+        redirects: vec![entry.clone()],
+    };
+    let (_, report) = parse_document(&serde_json::to_string(&document)?, Format::Json)?;
+
+    let errors: Vec<&str> = report
+        .diagnostics
+        .iter()
+        .filter(|d| d.kind.is_error())
+        .map(|d| d.message.as_str())
+        .collect();
+    if !errors.is_empty() {
+        bail!("{}", errors.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[tokio::test]
+    async fn test_redirect_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RedirectStore::new(dir.path().join("store.json"));
+        assert!(store.list().await.unwrap().is_empty());
+
+        let foo = RedirectEntry::new("foo", "https://foo.bar");
+        assert!(store.create(foo.clone()).await.unwrap());
+        assert!(!store.create(foo.clone()).await.unwrap());
+        assert!(store
+            .create(RedirectEntry::new("bar", "https://bar.baz"))
+            .await
+            .unwrap());
+        let created = store.get("foo").await.unwrap().unwrap().metadata;
+        assert!(created.created.is_some());
+        assert_eq!(created.updated, created.created);
+
+        let mut updated = RedirectEntry::new("foo", "https://foo.baz");
+        updated.status = Some(302);
+        assert!(store.update(updated.clone()).await.unwrap());
+        assert!(!store
+            .update(RedirectEntry::new("missing", "https://foo.baz"))
+            .await
+            .unwrap());
+
+        // Updates keep the creation time of the entry they replace.
+        let updated = store.get("foo").await.unwrap().unwrap();
+        assert_eq!(updated.url, "https://foo.baz");
+        assert_eq!(updated.metadata.created, created.created);
+        assert!(updated.metadata.updated.is_some());
+
+        assert!(store.delete("bar").await.unwrap());
+        assert!(!store.delete("bar").await.unwrap());
+
+        // Changes are persisted, so a new store for the same file sees them.
+        let store = RedirectStore::new(store.path());
+        assert_eq!(store.list().await.unwrap(), vec![updated]);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_store_fetch() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RedirectStore::new(dir.path().join("store.json"));
+        store
+            .create(RedirectEntry::new("foo", "https://foo.bar"))
+            .await
+            .unwrap();
+
+        let document = store.fetch().await.unwrap();
+        let (redirects, report) = parse_document(&document.contents, document.format).unwrap();
+        assert!(report.is_empty());
+        assert_eq!(redirects.get("foo").unwrap().url, "https://foo.bar");
+        assert!(store.location().starts_with("store://"));
+        assert!(!store.requires_signature());
+    }
+
+    #[tokio::test]
+    async fn test_redirect_store_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        let store = RedirectStore::new(&path);
+        assert!(store.list().await.is_err());
+        assert!(store
+            .create(RedirectEntry::new("foo", "https://foo.bar"))
+            .await
+            .is_err());
+    }
+
+    #[rstest]
+    #[case(RedirectEntry::new("foo", "https://foo.bar"), true)]
+    #[case(RedirectEntry::new("foo", "/bar"), true)]
+    #[case(RedirectEntry::new("", "https://foo.bar"), false)]
+    #[case(RedirectEntry::new("foo", "not-a-url"), false)]
+    #[case(RedirectEntry::new("^/(unclosed", "https://foo.bar"), false)]
+    #[case(RedirectEntry { status: Some(200), ..RedirectEntry::new("foo", "https://foo.bar") }, false)]
+    fn test_validate_entry(#[case] entry: RedirectEntry, #[case] valid: bool) {
+        assert_eq!(validate_entry(&entry).is_ok(), valid);
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{bail, Result};
+use axum::http::{header, HeaderMap, HeaderValue};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// The prefix of the cookie used to remember which variant of a redirect a visitor was sent to.
+const VARIANT_COOKIE_PREFIX: &str = "servy_variant_";
+
+/// How long a visitor keeps seeing the same variant of a redirect, in seconds.
+const VARIANT_COOKIE_MAX_AGE: u64 = 30 * 24 * 60 * 60;
+
+/// One of several destinations for a redirect which splits traffic by weight.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeightedVariant {
+    /// A name for the variant, used in metrics and to remember a visitor's assignment.
+    pub label: String,
+    pub url: String,
+    /// The relative share of traffic the variant receives.
+    pub weight: u32,
+}
+
+/// Check that the labels of a redirect's variants are unique, and can be stored in a cookie as
+/// they are: only ASCII letters, digits, `-` and `_` are allowed.
+pub fn validate_variants(variants: &[WeightedVariant]) -> Result<()> {
+    let mut labels = HashSet::new();
+    for variant in variants {
+        let label = &variant.label;
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if label.is_empty() || !label.chars().all(valid) {
+            bail!("invalid variant label '{label}': only letters, digits, '-' and '_' are allowed");
+        }
+        if !labels.insert(label) {
+            bail!("duplicate variant label '{label}'");
+        }
+    }
+    Ok(())
+}
+
+/// Choose a variant for a request. If the request carries a cookie assigning it to one of the
+/// variants, that variant is used, otherwise one is picked at random according to the weights.
+/// Returns `None` if there are no variants with a non-zero weight.
+pub fn choose_variant<'a>(
+    variants: &'a [WeightedVariant],
+    alias: &str,
+    headers: &HeaderMap,
+) -> Option<&'a WeightedVariant> {
+    let assigned = cookie_value(headers, &variant_cookie_name(alias))
+        .and_then(|label| variants.iter().find(|v| v.label == label && v.weight > 0));
+
+    assigned.or_else(|| {
+        // Sum the weights in a wider type, as the weights of several variants may not fit in u32.
+        let total: u64 = variants.iter().map(|v| u64::from(v.weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        pick_variant(variants, rand::rng().random_range(0..total))
+    })
+}
+
+/// Pick the variant which a given roll in the range `0..total_weight` lands on.
+fn pick_variant(variants: &[WeightedVariant], mut roll: u64) -> Option<&WeightedVariant> {
+    variants.iter().find(|v| {
+        let weight = u64::from(v.weight);
+        if roll < weight {
+            true
+        } else {
+            roll -= weight;
+            false
+        }
+    })
+}
+
+/// Construct a `Set-Cookie` header value which assigns a visitor to a variant of a redirect.
+pub fn variant_cookie(alias: &str, label: &str) -> Option<HeaderValue> {
+    let cookie = format!(
+        "{}={label}; Path=/; Max-Age={VARIANT_COOKIE_MAX_AGE}; HttpOnly; SameSite=Lax",
+        variant_cookie_name(alias)
+    );
+    HeaderValue::from_str(&cookie).ok()
+}
+
+/// Return the name of the cookie used to remember the variant of a given alias. Characters
+/// which are not valid in a cookie name are replaced with underscores.
+fn variant_cookie_name(alias: &str) -> String {
+    let alias: String = alias
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{VARIANT_COOKIE_PREFIX}{alias}")
+}
+
+/// Return the value of a named cookie from the request headers.
+fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| match pair.trim().split_once('=') {
+            Some((key, value)) if key == name => Some(value),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn variants() -> Vec<WeightedVariant> {
+        vec![
+            WeightedVariant {
+                label: "control".to_string(),
+                url: "https://example.com/a".to_string(),
+                weight: 90,
+            },
+            WeightedVariant {
+                label: "disabled".to_string(),
+                url: "https://example.com/x".to_string(),
+                weight: 0,
+            },
+            WeightedVariant {
+                label: "experiment".to_string(),
+                url: "https://example.com/b".to_string(),
+                weight: 10,
+            },
+        ]
+    }
+
+    #[rstest]
+    #[case(0, Some("control"))]
+    #[case(89, Some("control"))]
+    #[case(90, Some("experiment"))]
+    #[case(99, Some("experiment"))]
+    #[case(100, None)]
+    fn test_pick_variant(#[case] roll: u64, #[case] expected: Option<&str>) {
+        let variants = variants();
+        let variant = pick_variant(&variants, roll);
+        assert_eq!(variant.map(|v| v.label.as_str()), expected);
+    }
+
+    #[test]
+    fn test_choose_variant_large_weights() {
+        let variants: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|label| WeightedVariant {
+                label: label.to_string(),
+                url: format!("https://example.com/{label}"),
+                weight: 4_000_000_000,
+            })
+            .collect();
+
+        // The total weight doesn't fit in a u32, but both variants can still be chosen.
+        assert_eq!(pick_variant(&variants, 4_000_000_000).unwrap().label, "b");
+        for _ in 0..20 {
+            assert!(choose_variant(&variants, "signup", &HeaderMap::new()).is_some());
+        }
+    }
+
+    #[rstest]
+    #[case(&["control", "experiment-2", "new_flow"], true)]
+    #[case(&["control", ""], false)]
+    #[case(&["a; Domain=evil; Path=/"], false)]
+    #[case(&["two words"], false)]
+    #[case(&["café"], false)]
+    #[case(&["control", "control"], false)]
+    fn test_validate_variants(#[case] labels: &[&str], #[case] valid: bool) {
+        let variants: Vec<_> = labels
+            .iter()
+            .map(|label| WeightedVariant {
+                label: label.to_string(),
+                url: "https://example.com".to_string(),
+                weight: 1,
+            })
+            .collect();
+        assert_eq!(validate_variants(&variants).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case("servy_variant_signup=experiment", "experiment")]
+    #[case("theme=dark; servy_variant_signup=control", "control")]
+    fn test_choose_variant_sticky(#[case] cookie: &str, #[case] expected: &str) {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+
+        let variants = variants();
+        for _ in 0..20 {
+            let variant = choose_variant(&variants, "signup", &headers).unwrap();
+            assert_eq!(variant.label, expected);
+        }
+    }
+
+    #[rstest]
+    #[case("servy_variant_signup=disabled")]
+    #[case("servy_variant_signup=unknown")]
+    #[case("servy_variant_other=experiment")]
+    fn test_choose_variant_ignores_invalid_assignment(#[case] cookie: &str) {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+
+        let variants = variants();
+        let variant = choose_variant(&variants, "signup", &headers).unwrap();
+        assert_ne!(variant.label, "disabled");
+    }
+
+    #[test]
+    fn test_choose_variant_no_weight() {
+        let variants = vec![WeightedVariant {
+            label: "a".to_string(),
+            url: "https://example.com/a".to_string(),
+            weight: 0,
+        }];
+        assert!(choose_variant(&variants, "signup", &HeaderMap::new()).is_none());
+    }
+
+    #[rstest]
+    #[case("signup", "servy_variant_signup")]
+    #[case("docs/*", "servy_variant_docs__")]
+    #[case("^/blog/(.*)$", "servy_variant___blog______")]
+    fn test_variant_cookie_name(#[case] alias: &str, #[case] expected: &str) {
+        assert_eq!(variant_cookie_name(alias), expected);
+    }
+
+    #[test]
+    fn test_variant_cookie() {
+        let cookie = variant_cookie("signup", "experiment").unwrap();
+        assert_eq!(
+            cookie,
+            "servy_variant_signup=experiment; Path=/; Max-Age=2592000; HttpOnly; SameSite=Lax"
+        );
+    }
+}
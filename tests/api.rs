@@ -0,0 +1,190 @@
+mod common;
+use common::servy_with_config;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use serde_json::{json, Value};
+use servy::Config;
+use std::path::Path;
+
+const TOKEN: &str = "test-token";
+
+fn servy_with_store(store_path: &Path) -> TestServer {
+    let mut config = Config::default_with_redirects("");
+    config.store_path = Some(store_path.to_path_buf());
+    config.api_token = Some(TOKEN.to_string());
+    servy_with_config(config)
+}
+
+#[tokio::test]
+async fn test_api_requires_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = servy_with_store(&dir.path().join("store.json"));
+
+    let response = app.get("/_api/redirects").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    response.assert_header("www-authenticate", "Bearer");
+
+    let response = app
+        .get("/_api/redirects")
+        .authorization_bearer("wrong-token")
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_api_disabled_without_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default_with_redirects("");
+    config.store_path = Some(dir.path().join("store.json"));
+    let app = servy_with_config(config);
+
+    let response = app.get("/_api/redirects").authorization_bearer(TOKEN).await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_manage_redirects() {
+    let dir = tempfile::tempdir().unwrap();
+    let store_path = dir.path().join("store.json");
+    let app = servy_with_store(&store_path);
+
+    let response = app
+        .post("/_api/redirects")
+        .authorization_bearer(TOKEN)
+        .json(&json!({ "alias": "foo", "url": "https://foo.bar" }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    response.assert_header("location", "/_api/redirects/foo");
+
+    let response = app.get("/foo").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header("location", "https://foo.bar");
+
+    let response = app
+        .post("/_api/redirects")
+        .authorization_bearer(TOKEN)
+        .json(&json!({ "alias": "foo", "url": "https://foo.baz" }))
+        .await;
+    response.assert_status(StatusCode::CONFLICT);
+
+    let response = app
+        .put("/_api/redirects/foo")
+        .authorization_bearer(TOKEN)
+        .json(&json!({ "alias": "foo", "url": "https://foo.baz", "status": 302 }))
+        .await;
+    response.assert_status_ok();
+
+    let response = app.get("/foo").await;
+    response.assert_status(StatusCode::FOUND);
+    response.assert_header("location", "https://foo.baz");
+
+    let response = app
+        .get("/_api/redirects/foo")
+        .authorization_bearer(TOKEN)
+        .await;
+    let entry = response.json::<Value>();
+    assert_eq!(entry["url"], "https://foo.baz");
+    assert_eq!(entry["status"], 302);
+    assert!(entry["created"].is_string());
+    assert!(entry["updated"].is_string());
+
+    // Changes are saved, so a new instance using the same store serves them.
+    let restarted = servy_with_store(&store_path);
+    restarted
+        .get("/foo")
+        .await
+        .assert_header("location", "https://foo.baz");
+
+    let response = app
+        .delete("/_api/redirects/foo")
+        .authorization_bearer(TOKEN)
+        .await;
+    response.assert_status(StatusCode::NO_CONTENT);
+
+    app.get("/foo").await.assert_status(StatusCode::NOT_FOUND);
+
+    let response = app.get("/_api/redirects").authorization_bearer(TOKEN).await;
+    response.assert_json(&json!({ "redirects": [] }));
+}
+
+#[tokio::test]
+async fn test_api_rejects_invalid_redirects() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = servy_with_store(&dir.path().join("store.json"));
+
+    let response = app
+        .post("/_api/redirects")
+        .authorization_bearer(TOKEN)
+        .json(&json!({ "alias": "foo", "url": "not-a-url" }))
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.json::<Value>()["error"]
+        .as_str()
+        .unwrap()
+        .contains("not-a-url"));
+
+    let response = app
+        .put("/_api/redirects/foo")
+        .authorization_bearer(TOKEN)
+        .json(&json!({ "alias": "bar", "url": "https://bar.baz" }))
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    for response in [
+        app.get("/_api/redirects/missing")
+            .authorization_bearer(TOKEN)
+            .await,
+        app.put("/_api/redirects/missing")
+            .authorization_bearer(TOKEN)
+            .json(&json!({ "alias": "missing", "url": "https://foo.bar" }))
+            .await,
+        app.delete("/_api/redirects/missing")
+            .authorization_bearer(TOKEN)
+            .await,
+    ] {
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn test_api_shorten() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default_with_redirects("");
+    config.store_path = Some(dir.path().join("store.json"));
+    config.api_token = Some(TOKEN.to_string());
+    config.public_url = Some("https://go.example".to_string());
+    config.short_key_length = 8;
+    let app = servy_with_config(config);
+
+    let response = app
+        .post("/_api/shorten")
+        .authorization_bearer(TOKEN)
+        .json(&json!({ "url": "https://foo.bar/a/long/link", "status": 302 }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+
+    let link = response.json::<Value>();
+    let alias = link["alias"].as_str().unwrap();
+    assert_eq!(alias.len(), 8);
+    assert_eq!(link["url"], "https://foo.bar/a/long/link");
+    assert_eq!(link["short_url"], format!("https://go.example/{alias}"));
+    response.assert_header("location", format!("https://go.example/{alias}"));
+
+    let response = app.get(&format!("/{alias}")).await;
+    response.assert_status(StatusCode::FOUND);
+    response.assert_header("location", "https://foo.bar/a/long/link");
+
+    let response = app
+        .post("/_api/shorten")
+        .authorization_bearer(TOKEN)
+        .json(&json!({ "url": "not-a-url" }))
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = app
+        .post("/_api/shorten")
+        .json(&json!({ "url": "https://foo.bar" }))
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
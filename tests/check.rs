@@ -0,0 +1,78 @@
+use std::process::{Command, Output};
+
+fn servy_check(location: &str) -> Output {
+    servy_check_all(&[location])
+}
+
+fn servy_check_all(locations: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_servy"))
+        .arg("check")
+        .args(locations)
+        .output()
+        .expect("failed to run servy check")
+}
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+#[test]
+fn test_check_valid_file() {
+    let output = servy_check(&fixture("redirects"));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("2 redirects defined, 0 errors, 0 warnings"));
+}
+
+#[test]
+fn test_check_invalid_file() {
+    let output = servy_check(&fixture("invalid_redirects"));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(!output.status.success());
+    assert!(stdout.contains("warning: line 2: duplicate alias 'foo'"));
+    assert!(stdout.contains("warning: line 3: alias 'nested' is an embedded asset"));
+    assert!(stdout.contains("error: line 4: expected an alias and a URL"));
+    assert!(stdout.contains("error: line 5: invalid url 'not-a-url'"));
+    assert!(stdout.contains("2 redirects defined, 2 errors, 2 warnings"));
+}
+
+#[test]
+fn test_check_path_with_spaces() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("my redirects");
+    std::fs::copy(fixture("redirects"), &path).unwrap();
+
+    let output = servy_check(path.to_str().unwrap());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("2 redirects defined, 0 errors, 0 warnings"));
+}
+
+#[test]
+fn test_check_multiple_files() {
+    let output = servy_check_all(&[&fixture("redirects"), &fixture("invalid_redirects")]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(!output.status.success());
+    assert!(stdout.contains("error: line 5: invalid url 'not-a-url'"));
+}
+
+#[test]
+fn test_check_missing_file() {
+    let output = servy_check("/this/file/does/not/exist");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_check_usage() {
+    let output = Command::new(env!("CARGO_BIN_EXE_servy"))
+        .arg("check")
+        .output()
+        .expect("failed to run servy check");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: servy"));
+}
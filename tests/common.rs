@@ -1,7 +1,9 @@
 use axum_test::TestServer;
 use mockito::{Server, ServerGuard};
-use servy::{servy_router, AppContext, Config};
+use servy::{servy_router, AppContext, Config, Format, StaticSource};
+use std::sync::Arc;
 
+#[allow(dead_code)]
 pub async fn mock_redirect_source() -> ServerGuard {
     let mut server = Server::new_async().await;
 
@@ -15,7 +17,7 @@ pub async fn mock_redirect_source() -> ServerGuard {
 
     server
         .mock("GET", "/mock_redirects")
-        .match_query(mockito::Matcher::Regex("cachebust=[0-9]+".into()))
+        .match_query(mockito::Matcher::Missing)
         .with_body(redirects.join("\n"))
         .create_async()
         .await;
@@ -23,9 +25,32 @@ pub async fn mock_redirect_source() -> ServerGuard {
     server
 }
 
+#[allow(dead_code)]
 pub fn servy(redirect_source: &str) -> TestServer {
     let config = Config::default_with_redirects(redirect_source);
     let ctx = AppContext::new(config);
     let router = servy_router(ctx).expect("failed to initialise servy router");
     TestServer::new(router).expect("failed to bootstrap servy test server")
 }
+
+#[allow(dead_code)]
+pub fn servy_with_config(config: Config) -> TestServer {
+    let ctx = AppContext::new(config);
+    let router = servy_router(ctx).expect("failed to initialise servy router");
+    TestServer::new(router).expect("failed to bootstrap servy test server")
+}
+
+#[allow(dead_code)]
+pub fn servy_inline(config: Config, redirects: &str) -> TestServer {
+    let ctx = AppContext::with_source(config, Arc::new(StaticSource::new(redirects)));
+    let router = servy_router(ctx).expect("failed to initialise servy router");
+    TestServer::new(router).expect("failed to bootstrap servy test server")
+}
+
+#[allow(dead_code)]
+pub fn servy_inline_with_format(config: Config, redirects: &str, format: Format) -> TestServer {
+    let source = StaticSource::with_format(redirects, format);
+    let ctx = AppContext::with_source(config, Arc::new(source));
+    let router = servy_router(ctx).expect("failed to initialise servy router");
+    TestServer::new(router).expect("failed to bootstrap servy test server")
+}
@@ -11,7 +11,8 @@ use servy::metrics_router;
 #[tokio::test]
 async fn test_redirects_defined_metric() {
     let server = mock_redirect_source().await;
-    let app = servy(format!("{}{}", server.url(), "/mock_redirects").as_str());
+    let redirects_url = format!("{}{}", server.url(), "/mock_redirects");
+    let app = servy(&redirects_url);
 
     let router = metrics_router().expect("failed to initialise servy metrics router");
     let metrics_app =
@@ -42,14 +43,40 @@ async fn test_redirects_defined_metric() {
         "servy_redirects_served{alias=\"bar\"} 1",
         "servy_redirects_served{alias=\"foo\"} 1",
         //
+        "# HELP servy_signature_failures The number of redirects lists rejected because their signature could not be verified",
+        "# TYPE servy_signature_failures counter",
+        "servy_signature_failures 0",
+        //
         "# HELP servy_redirects_defined The number of redirects defined",
         "# TYPE servy_redirects_defined gauge",
         "servy_redirects_defined 2",
+        //
+        "# HELP servy_redirect_diagnostics The number of problems found in the redirects list, by kind",
+        "# TYPE servy_redirect_diagnostics gauge",
+        "servy_redirect_diagnostics{kind=\"field_count\"} 2",
+        "servy_redirect_diagnostics{kind=\"invalid_url\"} 0",
+        //
+        "# HELP servy_degraded Whether redirects are being served from the cache as the source is unavailable",
+        "# TYPE servy_degraded gauge",
+        "servy_degraded 0",
+        //
+        "# HELP servy_source_up Whether the last refresh of redirects from each source succeeded",
+        "# TYPE servy_source_up gauge",
+        //
+        "# HELP servy_source_redirects_defined The number of redirects defined by each source",
+        "# TYPE servy_source_redirects_defined gauge",
     ];
 
     for l in expected_lines {
         metrics_response.assert_text_contains(l);
     }
+    metrics_response.assert_text_contains(format!(
+        "servy_source_redirects_defined{{source=\"{redirects_url}\"}} 2"
+    ));
+    metrics_response
+        .assert_text_contains(format!("servy_source_up{{source=\"{redirects_url}\"}} 1"));
+    // The total is only reported without labels, so that summing it doesn't count twice.
+    assert!(!metrics_response.text().contains("servy_redirects_defined{"));
 
     // Ensure that the metrics endpoint responds
     metrics_response.assert_status(StatusCode::OK);
@@ -1,7 +1,12 @@
 mod common;
-use common::{mock_redirect_source, servy};
+use common::{
+    mock_redirect_source, servy, servy_inline, servy_inline_with_format, servy_with_config,
+};
 
-use axum::http::StatusCode;
+use axum::http::{self, StatusCode};
+use serde_json::json;
+use servy::{Config, Format, QueryPolicy};
+use std::time::Duration;
 
 #[tokio::test]
 async fn test_bad_redirect_source_error() {
@@ -32,6 +37,65 @@ async fn test_known_redirect_trailing_slash() {
     response.assert_header("location", "http://foo.bar");
 }
 
+#[tokio::test]
+async fn test_multiple_redirect_sources() {
+    let mut server = mock_redirect_source().await;
+    server
+        .mock("GET", "/override_redirects")
+        .with_body("foo http://foo.override\nqux http://qux.quux")
+        .create_async()
+        .await;
+
+    // The first source takes precedence where both define an alias.
+    let app = servy(&format!(
+        "{url}/override_redirects {url}/mock_redirects",
+        url = server.url()
+    ));
+
+    for (path, location) in [
+        ("/foo", "http://foo.override"),
+        ("/bar", "http://bar.baz"),
+        ("/qux", "http://qux.quux"),
+    ] {
+        let response = app.get(path).await;
+        response.assert_status(StatusCode::PERMANENT_REDIRECT);
+        response.assert_header("location", location);
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_redirect_solved_with_refresh_despite_failing_source() {
+    let mut server = mock_redirect_source().await;
+    server
+        .mock("GET", "/failing_redirects")
+        .with_status(500)
+        .create_async()
+        .await;
+    let mut config = Config::default_with_redirects(&format!(
+        "{url}/failing_redirects {url}/mock_redirects",
+        url = server.url()
+    ));
+    config.miss_refresh_interval = Duration::ZERO;
+    config.negative_cache_ttl = Duration::ZERO;
+    let app = servy_with_config(config);
+
+    app.get("/foo")
+        .await
+        .assert_status(StatusCode::PERMANENT_REDIRECT);
+
+    server
+        .mock("GET", "/mock_redirects")
+        .match_query(mockito::Matcher::Missing)
+        .with_body("baz http://baz.qux")
+        .create_async()
+        .await;
+
+    // The healthy source is applied and served, although the other still fails to refresh.
+    let response = app.get("/baz").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header("location", "http://baz.qux");
+}
+
 #[tokio::test]
 async fn test_unknown_redirect() {
     let server = mock_redirect_source().await;
@@ -45,7 +109,10 @@ async fn test_unknown_redirect() {
 #[tokio::test]
 async fn test_unknown_redirect_solved_with_refresh() {
     let mut server = mock_redirect_source().await;
-    let app = servy(format!("{}{}", server.url(), "/mock_redirects").as_str());
+    let mut config = Config::default_with_redirects(&format!("{}/mock_redirects", server.url()));
+    config.miss_refresh_interval = Duration::ZERO;
+    config.negative_cache_ttl = Duration::ZERO;
+    let app = servy_with_config(config);
 
     let response = app.get("/baz").await;
     response.assert_status(StatusCode::NOT_FOUND);
@@ -53,7 +120,7 @@ async fn test_unknown_redirect_solved_with_refresh() {
     // Now repopulate the redirects in the upstream source with a new map.
     server
         .mock("GET", "/mock_redirects")
-        .match_query(mockito::Matcher::Regex("cachebust=[0-9]+".into()))
+        .match_query(mockito::Matcher::Missing)
         .with_body("baz http://baz.qux")
         .create_async()
         .await;
@@ -62,3 +129,456 @@ async fn test_unknown_redirect_solved_with_refresh() {
     response.assert_status(StatusCode::PERMANENT_REDIRECT);
     response.assert_header("location", "http://baz.qux");
 }
+
+#[tokio::test]
+async fn test_unknown_redirect_refresh_rate_limited() {
+    let mut server = mock_redirect_source().await;
+    let app = servy(format!("{}{}", server.url(), "/mock_redirects").as_str());
+
+    let response = app.get("/baz").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    let mock = server
+        .mock("GET", "/mock_redirects")
+        .match_query(mockito::Matcher::Any)
+        .with_body("baz http://baz.qux")
+        .expect(0)
+        .create_async()
+        .await;
+
+    // Neither the unknown path nor a different one triggers another refresh straight away.
+    app.get("/baz").await.assert_status(StatusCode::NOT_FOUND);
+    app.get("/qux").await.assert_status(StatusCode::NOT_FOUND);
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_unknown_redirect_not_remembered_when_rate_limited() {
+    let mut server = mock_redirect_source().await;
+    let mut config = Config::default_with_redirects(&format!("{}/mock_redirects", server.url()));
+    config.miss_refresh_interval = Duration::from_millis(200);
+    let app = servy_with_config(config);
+
+    // The first miss refreshes the redirects, so the second is inside the rate limit window.
+    app.get("/baz").await.assert_status(StatusCode::NOT_FOUND);
+    app.get("/qux").await.assert_status(StatusCode::NOT_FOUND);
+
+    server
+        .mock("GET", "/mock_redirects")
+        .match_query(mockito::Matcher::Missing)
+        .with_body("qux http://qux.quux")
+        .create_async()
+        .await;
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    // The path was never checked against refreshed redirects, so it is not remembered as unknown.
+    let response = app.get("/qux").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header("location", "http://qux.quux");
+}
+
+#[tokio::test]
+async fn test_known_redirect_from_file() {
+    let path = format!(
+        "file://{}/tests/fixtures/redirects",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let app = servy(&path);
+
+    let response = app.get("/bar").await;
+
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header("location", "http://bar.baz");
+}
+
+#[tokio::test]
+async fn test_known_redirect_from_structured_file() {
+    let path = format!(
+        "file://{}/tests/fixtures/redirects.yaml",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let app = servy(&path);
+
+    let response = app.get("/foo").await;
+
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header("location", "http://foo.bar");
+}
+
+#[tokio::test]
+async fn test_redirect_status_codes() {
+    let config = Config::default_with_redirects("");
+    let app = servy_inline(
+        config,
+        "foo http://foo.bar\ntalk http://talk.example status=302\nmoved http://moved.example status=301",
+    );
+
+    let response = app.get("/foo").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+
+    let response = app.get("/talk").await;
+    response.assert_status(StatusCode::FOUND);
+    response.assert_header("location", "http://talk.example");
+
+    let response = app.get("/moved").await;
+    response.assert_status(StatusCode::MOVED_PERMANENTLY);
+}
+
+#[tokio::test]
+async fn test_redirect_default_status_code() {
+    let config = Config {
+        default_status: StatusCode::TEMPORARY_REDIRECT,
+        ..Config::default_with_redirects("")
+    };
+    let app = servy_inline(config, "foo http://foo.bar\nbar http://bar.baz status=308");
+
+    let response = app.get("/foo").await;
+    response.assert_status(StatusCode::TEMPORARY_REDIRECT);
+
+    let response = app.get("/bar").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+}
+
+#[tokio::test]
+async fn test_prefix_redirect() {
+    let config = Config::default_with_redirects("");
+    let app = servy_inline(config, "docs/* https://docs.example.com/*");
+
+    let response = app.get("/docs/guide/install").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header("location", "https://docs.example.com/guide/install");
+
+    let response = app.get("/docs").await;
+    response.assert_header("location", "https://docs.example.com/");
+
+    // Encoded characters in the forwarded path stay encoded, rather than changing the target.
+    for (path, location) in [
+        ("/docs/a%3Fb%23c", "https://docs.example.com/a%3Fb%23c"),
+        ("/docs/a%0d%0aX:y", "https://docs.example.com/a%0D%0AX:y"),
+        ("/docs/a%0Ab", "https://docs.example.com/a%0Ab"),
+    ] {
+        let response = app.get(path).await;
+        response.assert_status(StatusCode::PERMANENT_REDIRECT);
+        response.assert_header("location", location);
+    }
+}
+
+#[tokio::test]
+async fn test_regex_redirect() {
+    let config = Config::default_with_redirects("");
+    let app = servy_inline(
+        config,
+        r"^/blog/(\d{4})/(.*)$ -> https://new.example.com/posts/$2?year=$1",
+    );
+
+    let response = app.get("/blog/2019/hello-world").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header(
+        "location",
+        "https://new.example.com/posts/hello-world?year=2019",
+    );
+
+    // Captured text stays encoded, rather than adding to the query string or fragment.
+    let response = app.get("/blog/2019/a%3Fb%23c%0D").await;
+    response.assert_header(
+        "location",
+        "https://new.example.com/posts/a%3Fb%23c%0D?year=2019",
+    );
+
+    let response = app.get("/blog/hello-world").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_redirect_query_policies() {
+    let config = Config::default_with_redirects("");
+    let app = servy_inline(
+        config,
+        &[
+            "drop http://example.com/?a=1",
+            "pass http://example.com/?a=1 query=pass",
+            "merge http://example.com/?a=1 query=merge",
+        ]
+        .join("\n"),
+    );
+
+    let response = app.get("/drop?ref=twitter").await;
+    response.assert_header("location", "http://example.com/?a=1");
+
+    let response = app.get("/pass?ref=twitter").await;
+    response.assert_header("location", "http://example.com/?ref=twitter");
+
+    let response = app.get("/merge?ref=twitter").await;
+    response.assert_header("location", "http://example.com/?a=1&ref=twitter");
+}
+
+#[tokio::test]
+async fn test_redirect_default_query_policy() {
+    let config = Config {
+        query_policy: QueryPolicy::Merge,
+        ..Config::default_with_redirects("")
+    };
+    let app = servy_inline(
+        config,
+        "foo http://foo.bar/?a=1\nbar http://bar.baz/ query=drop",
+    );
+
+    let response = app.get("/foo?ref=twitter").await;
+    response.assert_header("location", "http://foo.bar/?a=1&ref=twitter");
+
+    let response = app.get("/bar?ref=twitter").await;
+    response.assert_header("location", "http://bar.baz/");
+}
+
+#[tokio::test]
+async fn test_host_scoped_redirects() {
+    let config = Config::default_with_redirects("");
+    let app = servy_inline(
+        config,
+        &[
+            "foo http://any.example",
+            "go.example.com foo http://go.example.com",
+            "example.org foo http://example.org",
+        ]
+        .join("\n"),
+    );
+
+    let response = app
+        .get("/foo")
+        .add_header(http::header::HOST, "go.example.com")
+        .await;
+    response.assert_header("location", "http://go.example.com");
+
+    let response = app
+        .get("/foo")
+        .add_header(http::header::HOST, "example.org:443")
+        .await;
+    response.assert_header("location", "http://example.org");
+
+    let response = app
+        .get("/foo")
+        .add_header(http::header::HOST, "unknown.example")
+        .await;
+    response.assert_header("location", "http://any.example");
+}
+
+#[tokio::test]
+async fn test_conditional_redirect() {
+    let config = Config::default_with_redirects("");
+    let redirects = r#"
+redirects:
+  - alias: app
+    url: https://example.com/app
+    targets:
+      - platform: ios
+        url: https://apps.apple.com/app
+      - language: de
+        url: https://example.com/de/app
+"#;
+    let app = servy_inline_with_format(config, redirects, Format::Yaml);
+
+    let response = app
+        .get("/app")
+        .add_header(
+            http::header::USER_AGENT,
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0)",
+        )
+        .await;
+    response.assert_header("location", "https://apps.apple.com/app");
+    response.assert_header("vary", "Accept-Language, User-Agent");
+
+    let response = app
+        .get("/app")
+        .add_header(http::header::ACCEPT_LANGUAGE, "de-DE,en;q=0.5")
+        .await;
+    response.assert_header("location", "https://example.com/de/app");
+
+    let response = app.get("/app").await;
+    response.assert_header("location", "https://example.com/app");
+}
+
+#[tokio::test]
+async fn test_weighted_redirect_is_sticky() {
+    let config = Config::default_with_redirects("");
+    let redirects = r#"{"redirects": [{
+        "alias": "signup",
+        "url": "https://example.com/signup",
+        "variants": [
+            {"label": "a", "url": "https://example.com/a", "weight": 50},
+            {"label": "b", "url": "https://example.com/b", "weight": 50}
+        ]
+    }]}"#;
+    let app = servy_inline_with_format(config, redirects, Format::Json);
+
+    let response = app.get("/signup").await;
+    let cookie = response.cookie("servy_variant_signup");
+    let location = response.header("location");
+
+    for _ in 0..10 {
+        let response = app.get("/signup").add_cookie(cookie.clone()).await;
+        response.assert_header("location", location.clone());
+    }
+}
+
+#[tokio::test]
+async fn test_alias_redirect() {
+    let mut config = Config::default_with_redirects("");
+    config.max_alias_depth = 1;
+    let redirects = [
+        "github https://github.com/jnsgruk status=302",
+        "gh /github",
+        "g /gh",
+        "loop /loop",
+    ];
+    let app = servy_inline(config, &redirects.join("\n"));
+
+    let response = app.get("/gh").await;
+    response.assert_status(StatusCode::FOUND);
+    response.assert_header("location", "https://github.com/jnsgruk");
+
+    // The chain from `g` is longer than the maximum depth, so it is rejected.
+    let response = app.get("/g").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    let response = app.get("/loop").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_preview_redirect() {
+    let mut config = Config::default_with_redirects("");
+    config.public_url = Some("https://go.example/".to_string());
+    let redirects = ["github https://github.com/jnsgruk status=302 query=pass"];
+    let app = servy_inline(config, &redirects.join("\n"));
+
+    for path in ["/github+", "/github?preview", "/github?tab=repos&preview"] {
+        let response = app.get(path).await;
+        response.assert_status_ok();
+        response.assert_header("content-type", "text/html; charset=utf-8");
+        response.assert_text_contains(
+            r#"<meta property="og:url" content="https://go.example/github">"#,
+        );
+        response.assert_text_contains("<dt>Status</dt><dd>302 Found</dd>");
+    }
+
+    // The rest of the query string is applied to the target, as it would be when redirected.
+    let response = app.get("/github?tab=repos&preview").await;
+    response.assert_text_contains(r#"<a href="https://github.com/jnsgruk?tab=repos">"#);
+
+    // Previews of unknown aliases are not found.
+    let response = app.get("/unknown+").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_preview_alias_ending_in_suffix() {
+    let mut config = Config::default_with_redirects("");
+    config.public_url = Some("https://go.example/".to_string());
+    let redirects = ["c+ https://c.example", "c++ https://isocpp.org"];
+    let app = servy_inline(config, &redirects.join("\n"));
+
+    // Load the redirects, which happens on the first miss as they aren't loaded at startup here.
+    app.get("/unknown")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    // An alias which ends in a `+` is redirected, rather than previewing the alias without it.
+    let response = app.get("/c++").await;
+    response.assert_status(StatusCode::PERMANENT_REDIRECT);
+    response.assert_header("location", "https://isocpp.org");
+
+    for path in ["/c+++", "/c++?preview"] {
+        let response = app.get(path).await;
+        response.assert_status_ok();
+        response
+            .assert_text_contains(r#"<meta property="og:url" content="https://go.example/c++">"#);
+    }
+}
+
+#[tokio::test]
+async fn test_qr_code() {
+    let mut config = Config::default_with_redirects("");
+    config.public_url = Some("https://go.example/".to_string());
+    let app = servy_inline(config, "github https://github.com/jnsgruk");
+
+    let response = app.get("/_qr/github").await;
+    response.assert_status_ok();
+    response.assert_header("content-type", "image/svg+xml");
+    response.assert_text_contains("<svg");
+
+    // QR codes are only returned for known aliases.
+    let response = app.get("/_qr/unknown").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_links_json() {
+    let redirects = r#"
+redirects:
+  - alias: wiki
+    url: https://wiki.example.com
+    description: The team wiki
+    tags: [docs]
+    owner: platform
+    created: 2024-01-02T03:04:05Z
+  - alias: chat
+    url: https://chat.example.com
+"#;
+    let config = Config::default_with_redirects("");
+    let app = servy_inline_with_format(config, redirects, Format::Yaml);
+
+    // Redirects are loaded at startup by the server, but on the first miss in tests.
+    app.get("/chat")
+        .await
+        .assert_status(StatusCode::PERMANENT_REDIRECT);
+
+    let response = app.get("/_/links.json").await;
+    response.assert_status_ok();
+    response.assert_json(&json!({
+        "links": [
+            { "alias": "chat", "url": "https://chat.example.com" },
+            {
+                "alias": "wiki",
+                "url": "https://wiki.example.com",
+                "description": "The team wiki",
+                "tags": ["docs"],
+                "owner": "platform",
+                "created": "2024-01-02T03:04:05Z",
+            },
+        ]
+    }));
+}
+
+#[tokio::test]
+async fn test_links_page() {
+    let redirects = r#"
+redirects:
+  - alias: wiki
+    url: https://wiki.example.com
+    tags: [docs]
+  - alias: chat
+    url: https://chat.example.com
+"#;
+    let mut config = Config::default_with_redirects("");
+    config.public_url = Some("https://go.example".to_string());
+    let app = servy_inline_with_format(config, redirects, Format::Yaml);
+    app.get("/chat")
+        .await
+        .assert_status(StatusCode::PERMANENT_REDIRECT);
+
+    let response = app.get("/_/links").await;
+    response.assert_status_ok();
+    response.assert_header("content-type", "text/html; charset=utf-8");
+    response.assert_text_contains(r#"<a href="/chat">chat</a>"#);
+    response.assert_text_contains(r#"<a href="/wiki">wiki</a>"#);
+
+    // Searches match tags as well as aliases and targets.
+    let response = app.get("/_/links?q=docs").await;
+    response.assert_text_contains(r#"<a href="/wiki">wiki</a>"#);
+    assert!(!response.text().contains(r#"<a href="/chat">chat</a>"#));
+
+    let response = app.get("/_/opensearch.xml").await;
+    response.assert_status_ok();
+    response.assert_header("content-type", "application/opensearchdescription+xml");
+    response.assert_text_contains(r#"template="https://go.example/_/links?q={searchTerms}""#);
+}